@@ -16,17 +16,17 @@
 //! - Initializer will get back ownership of their token X account
 
 use anchor_lang::{prelude::*, accounts::account::Account};
-use anchor_spl::token::{self, CloseAccount, Mint, SetAuthority, TokenAccount, Transfer};
+use anchor_spl::token::{self, CloseAccount, Mint, SetAuthority, TokenAccount, Transfer, TransferChecked};
 use spl_token::instruction::AuthorityType;
 
 declare_id!("HavZRZtrzKHAs3RTxdm77hvzSL1aaZujVmn9k2mA4yqE");
 
+const ESCROW_PDA_SEED: &[u8] = b"escrow";
+
 #[program]
 pub mod escrow {
     use super::*;
 
-    const ESCROW_PDA_SEED: &[u8] = b"escrow";
-
     pub fn initialize_escrow(
         ctx: Context<InitializeEscrow>,
         _vault_account_bump: u8,
@@ -92,34 +92,115 @@ pub mod escrow {
         Ok(())
     }
 
-    pub fn exchange(ctx: Context<Exchange>) -> Result<()> {
-        let (_vault_authority, vault_authority_bump) =
-            Pubkey::find_program_address(&[ESCROW_PDA_SEED], ctx.program_id);
-        let authority_seeds = &[&ESCROW_PDA_SEED[..], &[vault_authority_bump]];
+    // max_taker_amount guards the taker against the stored taker_amount being more than they agreed to pay
+    pub fn exchange(ctx: Context<Exchange>, max_taker_amount: u64) -> Result<()> {
+        require!(
+            ctx.accounts.escrow_account.taker_amount <= max_taker_amount,
+            EscrowError::SlippageExceeded
+        );
 
-        // transfer y tokens from taker_deposit_token_account to initializer_deposit_token_account
+        execute_exchange(ctx)
+    }
+
+    pub fn top_up(ctx: Context<TopUp>, additional_amount: u64) -> Result<()> {
+        ctx.accounts.escrow_account.initializer_amount = ctx
+            .accounts
+            .escrow_account
+            .initializer_amount
+            .checked_add(additional_amount)
+            .ok_or(EscrowError::AmountOverflow)?;
+
+        // transfer the additional x tokens from initializer_deposit_token_account to PDA(vault_account)
         token::transfer(
-            ctx.accounts.into_transfer_to_initializer_context(),
-            ctx.accounts.escrow_account.taker_amount,
+            ctx.accounts.into_transfer_to_pda_context(),
+            additional_amount,
         )?;
 
-        // transfer x tokens from vault_account to taker_receive_token_account
-        token::transfer(
+        Ok(())
+    }
+
+    // exchange, but only if the stored amounts are still within the taker's limits
+    pub fn exchange_with_bounds(
+        ctx: Context<Exchange>,
+        min_initializer_amount: u64,
+        max_taker_amount: u64,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.escrow_account.initializer_amount >= min_initializer_amount,
+            EscrowError::InitializerAmountBelowMinimum
+        );
+        require!(
+            ctx.accounts.escrow_account.taker_amount <= max_taker_amount,
+            EscrowError::TakerAmountAboveMaximum
+        );
+
+        execute_exchange(ctx)
+    }
+}
+
+// the transfers and the close shared by exchange and exchange_with_bounds, which check the amounts first
+fn execute_exchange(ctx: Context<Exchange>) -> Result<()> {
+    let (_vault_authority, vault_authority_bump) =
+        Pubkey::find_program_address(&[ESCROW_PDA_SEED], ctx.program_id);
+    let authority_seeds = &[&ESCROW_PDA_SEED[..], &[vault_authority_bump]];
+
+    // transfer y tokens from taker_deposit_token_account to initializer_receive_token_account
+    token::transfer_checked(
+        ctx.accounts.into_transfer_checked_to_initializer_context(),
+        ctx.accounts.escrow_account.taker_amount,
+        ctx.accounts.taker_deposit_mint.decimals,
+    )?;
+
+    // transfer x tokens from vault_account to taker_receive_token_account
+    token::transfer_checked(
         ctx.accounts                                // &mut Exchange
-                .into_transfer_to_taker_context()     // CpiContext<Transfer>
-                .with_signer(&[&authority_seeds[..]]),       // signer_seeds
-            ctx.accounts.escrow_account.initializer_amount,
-        )?;
+            .into_transfer_checked_to_taker_context()     // CpiContext<TransferChecked>
+            .with_signer(&[&authority_seeds[..]]),       // signer_seeds
+        ctx.accounts.escrow_account.initializer_amount,
+        ctx.accounts.initializer_deposit_mint.decimals,
+    )?;
 
-        // close vault_account
-        token::close_account(
+    // close vault_account
+    token::close_account(
         ctx.accounts                                   // &mut Exchange
-                .into_close_context()             // CpiContext<CloseAccount>
-                .with_signer(&[&authority_seeds[..]]),
-        )?;
+            .into_close_context()             // CpiContext<CloseAccount>
+            .with_signer(&[&authority_seeds[..]]),
+    )?;
 
-        Ok(())
-    }
+    emit!(ExchangeExecuted {
+        initializer: ctx.accounts.escrow_account.initializer_key,
+        taker: *ctx.accounts.taker.key,
+        initializer_amount: ctx.accounts.escrow_account.initializer_amount,
+        taker_amount: ctx.accounts.escrow_account.taker_amount,
+    });
+
+    Ok(())
+}
+
+/// Emitted at the end of a successful `exchange`.
+///
+/// Logged as `Program data: <base64>`, which decodes to the 8 byte event discriminator
+/// followed by the borsh encoded fields in this order:
+/// `initializer`, `taker`, `initializer_amount` (X the taker received),
+/// `taker_amount` (Y the initializer received).
+#[event]
+pub struct ExchangeExecuted {
+    pub initializer: Pubkey,
+    pub taker: Pubkey,
+    pub initializer_amount: u64,
+    pub taker_amount: u64,
+}
+
+#[error_code]
+pub enum EscrowError {
+    #[msg("The escrow offers fewer tokens than the taker's minimum")]
+    InitializerAmountBelowMinimum,
+    #[msg("The escrow asks for more tokens than the taker's maximum")]
+    TakerAmountAboveMaximum,
+    #[msg("The escrow amount would overflow")]
+    AmountOverflow,
+    #[msg("The escrow asks for more tokens than the taker is willing to pay")]
+    SlippageExceeded,
 }
 
 /**
@@ -181,6 +262,26 @@ pub struct CancelEscrow<'info> {
     pub token_program: AccountInfo<'info>,
 }
 
+#[derive(Accounts)]
+pub struct TopUp<'info> {
+    #[account(signer)]
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub initializer: AccountInfo<'info>,
+    #[account(mut, seeds = [b"token-seed".as_ref()], bump)]
+    pub vault_account: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub initializer_deposit_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = escrow_account.initializer_key == *initializer.key,
+        constraint = escrow_account.initializer_deposit_token_account == *initializer_deposit_token_account.to_account_info().key,
+    )]
+    pub escrow_account: Box<Account<'info, EscrowAccount>>,
+    /// CHECK: only the SPL token program may move the initializer's tokens
+    #[account(constraint = token_program.key == &token::ID)]
+    pub token_program: AccountInfo<'info>,
+}
+
 // derive in Rust: allows new item to be automatically generated for data structures
 #[derive(Accounts)]
 pub struct Exchange<'info> {
@@ -213,6 +314,10 @@ pub struct Exchange<'info> {
     pub vault_authority: AccountInfo<'info>,
     /// CHECK: This is not dangerous because we don't read or write from this account
     pub token_program: AccountInfo<'info>,
+    // mint of the vault's x tokens, the token program rejects the transfer if it doesn't match
+    pub initializer_deposit_mint: Box<Account<'info, Mint>>,
+    // mint of the taker's y tokens
+    pub taker_deposit_mint: Box<Account<'info, Mint>>,
 }
 
 #[account]
@@ -224,6 +329,21 @@ pub struct EscrowAccount {
     pub taker_amount: u64,
 }
 
+// readable escrow state, handy when an integration test fails
+impl std::fmt::Display for EscrowAccount {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "EscrowAccount {{ initializer_key: {}, initializer_deposit_token_account: {}, initializer_receive_token_account: {}, initializer_amount: {}, taker_amount: {} }}",
+            self.initializer_key,
+            self.initializer_deposit_token_account,
+            self.initializer_receive_token_account,
+            self.initializer_amount,
+            self.taker_amount,
+        )
+    }
+}
+
 impl<'info> InitializeEscrow<'info> {
     // transfer x tokens from initializer_deposit_token_account to vault_account
     fn into_transfer_to_pda_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
@@ -249,6 +369,21 @@ impl<'info> InitializeEscrow<'info> {
     }
 }
 
+impl<'info> TopUp<'info> {
+    // transfer x tokens from initializer_deposit_token_account to vault_account
+    fn into_transfer_to_pda_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self
+                .initializer_deposit_token_account
+                .to_account_info()
+                .clone(),
+            to: self.vault_account.to_account_info().clone(),
+            authority: self.initializer.clone(),
+        };
+        CpiContext::new(self.token_program.clone(), cpi_accounts)
+    }
+}
+
 impl<'info> CancelEscrow<'info> {
     // generate CPI context for Transfer
     fn into_transfer_to_initializer_context(
@@ -278,11 +413,12 @@ impl<'info> CancelEscrow<'info> {
 }
 
 impl<'info> Exchange<'info> {
-    fn into_transfer_to_initializer_context(
+    fn into_transfer_checked_to_initializer_context(
         &self,
-    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
+    ) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
+        let cpi_accounts = TransferChecked {
             from: self.taker_deposit_token_account.to_account_info().clone(),
+            mint: self.taker_deposit_mint.to_account_info().clone(),
             to: self
                 .initializer_receive_token_account
                 .to_account_info()
@@ -293,9 +429,12 @@ impl<'info> Exchange<'info> {
         CpiContext::new(cpi_program, cpi_accounts)
     }
 
-    fn into_transfer_to_taker_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
+    fn into_transfer_checked_to_taker_context(
+        &self,
+    ) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
+        let cpi_accounts = TransferChecked {
             from: self.vault_account.to_account_info().clone(),
+            mint: self.initializer_deposit_mint.to_account_info().clone(),
             to: self.taker_receive_token_account.to_account_info().clone(),
             authority: self.vault_authority.clone(),
         };
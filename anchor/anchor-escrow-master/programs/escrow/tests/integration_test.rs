@@ -2,14 +2,19 @@ mod program_test;
 #[cfg(test)]
 mod test {
     use std::mem::size_of;
-    use program_test::{EscrowProgramTest, initialize_mint, initialize_ata, mint_some, airdrop, get_token_balance, get_rent_minimum_balance};
-    use solana_program_test::{tokio};
+    use program_test::{EscrowProgramTest, EscrowProgramTestConfig, TwoPartySetup, assert_distinct, ata, escrow_discriminator, initialize_mint, initialize_ata, mint_some, airdrop, airdrop_exact, get_token_balance, get_lamport_balance, get_rent_minimum_balance};
+    use solana_program_test::{tokio, BanksClientError};
 
     use super::*;
     use escrow;
-    use anchor_lang::{prelude::*, InstructionData};
-    use solana_sdk::{instruction::Instruction, system_instruction};
-    use solana_program::{system_program};
+    use anchor_lang::{prelude::*, Discriminator, InstructionData};
+    use solana_sdk::{
+        account::{AccountSharedData, WritableAccount},
+        instruction::{Instruction, InstructionError},
+        system_instruction,
+        transaction::TransactionError,
+    };
+    use solana_program::{program_pack::Pack, system_program};
     use {
         anchor_client::{
             solana_sdk::{
@@ -25,33 +30,194 @@ mod test {
     async fn test_escrow_success() {
         let mut pt = EscrowProgramTest::start_new().await;
 
-        let escrow_account = Keypair::new();
         let escrow_initializer_keypair = Keypair::new();
         let escrow_taker_keypair = Keypair::new();
+
+        // "A" minted to the initializer, "B" to the taker
+        let TwoPartySetup {
+            mint_a,
+            mint_b,
+            initializer_a_ata,
+            initializer_b_ata,
+            taker_a_ata,
+            taker_b_ata,
+        } = pt.setup_two_party_mints(&escrow_initializer_keypair, &escrow_taker_keypair).await;
+        pt.assert_token_balance(initializer_a_ata, 200).await;
+        pt.assert_token_balance(initializer_b_ata, 0).await;
+        pt.assert_token_balance(taker_a_ata, 0).await;
+        pt.assert_token_balance(taker_b_ata, 1000).await;
+        assert_eq!(pt.get_mint_decimals(mint_b).await, 3);
+        let (vault_authority, _authority_bump) = pt.vault_authority();
+
+        airdrop(&escrow_initializer_keypair.pubkey(), 1_000_000_000, &mut pt).await;
+        airdrop(&escrow_taker_keypair.pubkey(), 1_000_000_000, &mut pt).await;
+        let (escrow_account, vault_pda) = pt.create_escrow(
+            &escrow_initializer_keypair,
+            &mint_a,
+            &initializer_a_ata,
+            &initializer_b_ata,
+            100,
+            1000,
+        ).await;
+
+        pt.assert_vault_balance(100).await;
+        let escrow_state: escrow::EscrowAccount = pt.get_account_data(escrow_account).await;
+        assert_eq!(escrow_state.initializer_amount, 100);
+        assert_eq!(escrow_state.taker_amount, 1000);
+        pt.assert_token_balance(initializer_a_ata, 100).await;
+        pt.assert_token_balance(initializer_b_ata, 0).await;
+        
+        pt.process_tx_and_assert_ok(&[
+            Instruction{
+                program_id: pt.program_id,
+                accounts: escrow::accounts::Exchange { 
+                    taker: escrow_taker_keypair.pubkey(), 
+                    taker_deposit_token_account: taker_b_ata.key(), 
+                    taker_receive_token_account: taker_a_ata.key(), 
+                    initializer_deposit_token_account: initializer_a_ata.key(), 
+                    initializer_receive_token_account: initializer_b_ata.key(), 
+                    initializer: escrow_initializer_keypair.pubkey(), 
+                    escrow_account: escrow_account, 
+                    vault_account: vault_pda, 
+                    vault_authority: vault_authority, 
+                    token_program: spl_token::id(),
+                    initializer_deposit_mint: mint_a,
+                    taker_deposit_mint: mint_b,
+                }.to_account_metas(None),
+                data: escrow::instruction::Exchange {
+                    max_taker_amount: 1000,
+                }.data()
+            }
+        ], &[&escrow_taker_keypair]).await;
+        
+        pt.assert_token_balance(initializer_a_ata, 100).await;
+        pt.assert_token_balance(initializer_b_ata, 1000).await;
+        pt.assert_token_balance(taker_a_ata, 100).await;
+        pt.assert_token_balance(taker_b_ata, 0).await;
+        
+    }
+
+    #[tokio::test]
+    async fn test_vault_pdas_are_deterministic_and_off_curve() {
+        let pt = EscrowProgramTest::start_new().await;
+
+        let (vault_pda, vault_bump) = pt.vault_pda();
+        let (vault_authority, authority_bump) = pt.vault_authority();
+        assert_eq!(pt.vault_pda(), (vault_pda, vault_bump));
+        assert_eq!(pt.vault_authority(), (vault_authority, authority_bump));
+        assert_ne!(vault_pda, vault_authority);
+
+        // PDAs have no private key, they lie off the ed25519 curve
+        assert!(!vault_pda.is_on_curve());
+        assert!(!vault_authority.is_on_curve());
+        assert_eq!(
+            Pubkey::create_program_address(&[b"token-seed".as_ref(), &[vault_bump]], &pt.program_id),
+            Ok(vault_pda)
+        );
+        assert_eq!(
+            Pubkey::create_program_address(&[b"escrow".as_ref(), &[authority_bump]], &pt.program_id),
+            Ok(vault_authority)
+        );
+    }
+
+    #[test]
+    fn test_escrow_account_display() {
+        let escrow_account = escrow::EscrowAccount {
+            initializer_key: Pubkey::new_unique(),
+            initializer_deposit_token_account: Pubkey::new_unique(),
+            initializer_receive_token_account: Pubkey::new_unique(),
+            initializer_amount: 100,
+            taker_amount: 1000,
+        };
+        let formatted = escrow_account.to_string();
+        assert!(formatted.contains("initializer_amount: 100"));
+        assert!(formatted.contains(&escrow_account.initializer_key.to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_start_new_with_mint_decimals() {
+        let config = EscrowProgramTestConfig {
+            num_mints: 3,
+            mint_decimals: vec![0, 6, 9],
+            ..EscrowProgramTestConfig::default()
+        };
+        let mut pt = EscrowProgramTest::start_new_with_config(config).await;
+        pt.create_configured_mints(0).await;
+        assert_eq!(pt.mints.len(), 3);
+
+        for (mint, expected_decimals) in pt.mints.clone().iter().zip([0u8, 6, 9].iter()) {
+            assert_eq!(mint.decimals, *expected_decimals);
+            let mint_account = pt.context.banks_client.get_account(mint.pubkey).await.unwrap().unwrap();
+            let decimals = spl_token::state::Mint::unpack(&mint_account.data).unwrap().decimals;
+            assert_eq!(decimals, *expected_decimals);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_configured_mints() {
+        let mut pt = EscrowProgramTest::start_new_with_config(EscrowProgramTestConfig::default().with_num_mints(4)).await;
+        let mints = pt.create_configured_mints(6).await;
+        assert_eq!(mints.len(), 4);
+        assert_eq!(pt.mints.len(), 4);
+
+        let all_mints: Vec<Pubkey> = pt.mints.iter().map(|mint| mint.pubkey).collect();
+        assert_distinct(&all_mints);
+        for mint in mints {
+            assert_eq!(pt.get_mint_decimals(mint).await, 6);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mint_to_many() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let mint_keypair = Keypair::new();
+        initialize_mint(&mint_keypair, 0, &mut pt).await;
+
+        let mut recipients = vec![];
+        for amount in [10, 20, 30] {
+            let user = Keypair::new();
+            let user_ata = initialize_ata(&user.pubkey(), &mint_keypair.pubkey(), &mut pt).await;
+            recipients.push((user_ata, amount));
+        }
+
+        pt.mint_to_many(&mint_keypair.pubkey(), &recipients).await;
+        for (user_ata, amount) in recipients {
+            pt.assert_token_balance(user_ata, amount).await;
+        }
+        assert_eq!(pt.get_mint_supply(mint_keypair.pubkey()).await, 60);
+    }
+
+    #[tokio::test]
+    async fn test_ata_matches_initialize_ata() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let user = Keypair::new();
+        let mint_keypair = Keypair::new();
+        initialize_mint(&mint_keypair, 0, &mut pt).await;
+
+        let expected = ata(&user.pubkey(), &mint_keypair.pubkey());
+        let created = initialize_ata(&user.pubkey(), &mint_keypair.pubkey(), &mut pt).await;
+        assert_eq!(expected, created);
+    }
+
+    #[tokio::test]
+    async fn test_initialize_escrow_returns_logs() {
+        let mut pt = EscrowProgramTest::start_new().await;
+
+        let escrow_account = Keypair::new();
+        let escrow_initializer_keypair = Keypair::new();
         let mint_a_keypair = Keypair::new();
         let mint_b_keypair = Keypair::new();
-        let payer_pk = pt.context.payer.pubkey();
 
-        // Mint "A" Token to escrow_initializer
         initialize_mint(&mint_a_keypair, 0, &mut pt).await;
+        initialize_mint(&mint_b_keypair, 0, &mut pt).await;
         let initializer_a_ata = initialize_ata(&escrow_initializer_keypair.pubkey(),&mint_a_keypair.pubkey(),&mut pt).await;
-        let taker_a_ata = initialize_ata(&escrow_taker_keypair.pubkey(),&mint_a_keypair.pubkey(),&mut pt).await;
-        mint_some(&initializer_a_ata,&mint_a_keypair.pubkey(),&mut pt,200).await;
-        let balance_initializer_a_ata = get_token_balance(initializer_a_ata, &mut pt).await;
-        assert!(balance_initializer_a_ata == 200);
-
-        initialize_mint(&mint_b_keypair, 3, &mut pt).await;
         let initializer_b_ata = initialize_ata(&escrow_initializer_keypair.pubkey(),&mint_b_keypair.pubkey(),&mut pt).await;
-        let taker_b_ata = initialize_ata(&escrow_taker_keypair.pubkey(),&mint_b_keypair.pubkey(),&mut pt).await;
-        mint_some(&taker_b_ata, &mint_b_keypair.pubkey(), &mut pt, 1000).await;
-        // Create Vault PDA
-        let (vault_pda, pda_bump) = Pubkey::find_program_address(&[b"token-seed".as_ref()],&pt.program_id);
-        let (vault_authority, authority_bump) = Pubkey::find_program_address(&[b"escrow".as_ref()],&pt.program_id);
+        mint_some(&initializer_a_ata,&mint_a_keypair.pubkey(),&mut pt,200).await;
+        let (vault_pda, pda_bump) = pt.vault_pda();
 
         let escrow_rent_exempt_threshold = get_rent_minimum_balance(8 + size_of::<escrow::EscrowAccount>(), &mut pt).await;
         airdrop(&escrow_initializer_keypair.pubkey(), 1_000_000_000, &mut pt).await;
-        airdrop(&escrow_taker_keypair.pubkey(), 1_000_000_000, &mut pt).await;
-        pt.process_tx_and_assert_ok(&[
+        let logs = pt.process_tx_and_return_logs(&[
             system_instruction::create_account(
                 &escrow_initializer_keypair.pubkey(),
                 &escrow_account.pubkey(),
@@ -80,41 +246,614 @@ mod test {
             }
         ], &[&escrow_initializer_keypair, &escrow_account]).await;
 
-        let balance_vault_pda = get_token_balance(vault_pda.key(), &mut pt).await;
-        let balance_initializer_a_ata = get_token_balance(initializer_a_ata, &mut pt).await;
-        let balance_initializer_b_ata = get_token_balance(initializer_b_ata, &mut pt).await;
-        assert!(balance_vault_pda == 100);
-        assert!(balance_initializer_a_ata == 100);
-        assert!(balance_initializer_b_ata == 0);
-        
+        assert!(logs.iter().any(|log| log == "Program log: Instruction: InitializeEscrow"));
+    }
+
+    // an escrow offering 100 "A" for 1000 "B", with a funded taker ready to exchange
+    struct OpenEscrow {
+        escrow_account: Pubkey,
+        initializer: Keypair,
+        initializer_a_ata: Pubkey,
+        initializer_b_ata: Pubkey,
+        taker: Keypair,
+        taker_a_ata: Pubkey,
+        taker_b_ata: Pubkey,
+        mint_a: Pubkey,
+        mint_b: Pubkey,
+        vault_pda: Pubkey,
+        vault_authority: Pubkey,
+    }
+
+    async fn open_escrow(pt: &mut EscrowProgramTest) -> OpenEscrow {
+        let escrow_initializer_keypair = Keypair::new();
+        let escrow_taker_keypair = Keypair::new();
+        let mint_a_keypair = Keypair::new();
+        let mint_b_keypair = Keypair::new();
+        assert_distinct(&[
+            escrow_initializer_keypair.pubkey(),
+            escrow_taker_keypair.pubkey(),
+            mint_a_keypair.pubkey(),
+            mint_b_keypair.pubkey(),
+        ]);
+
+        initialize_mint(&mint_a_keypair, 0, pt).await;
+        initialize_mint(&mint_b_keypair, 0, pt).await;
+        let initializer_a_ata = initialize_ata(&escrow_initializer_keypair.pubkey(),&mint_a_keypair.pubkey(),pt).await;
+        let initializer_b_ata = initialize_ata(&escrow_initializer_keypair.pubkey(),&mint_b_keypair.pubkey(),pt).await;
+        let taker_a_ata = initialize_ata(&escrow_taker_keypair.pubkey(),&mint_a_keypair.pubkey(),pt).await;
+        let taker_b_ata = initialize_ata(&escrow_taker_keypair.pubkey(),&mint_b_keypair.pubkey(),pt).await;
+        mint_some(&initializer_a_ata,&mint_a_keypair.pubkey(),pt,200).await;
+        mint_some(&taker_b_ata,&mint_b_keypair.pubkey(),pt,1000).await;
+        let (vault_authority, _authority_bump) = pt.vault_authority();
+
+        airdrop(&escrow_initializer_keypair.pubkey(), 1_000_000_000, pt).await;
+        let (escrow_account, vault_pda) = pt.create_escrow(
+            &escrow_initializer_keypair,
+            &mint_a_keypair.pubkey(),
+            &initializer_a_ata,
+            &initializer_b_ata,
+            100,
+            1000,
+        ).await;
+
+        OpenEscrow {
+            escrow_account,
+            initializer: escrow_initializer_keypair,
+            initializer_a_ata,
+            initializer_b_ata,
+            taker: escrow_taker_keypair,
+            taker_a_ata,
+            taker_b_ata,
+            mint_a: mint_a_keypair.pubkey(),
+            mint_b: mint_b_keypair.pubkey(),
+            vault_pda,
+            vault_authority,
+        }
+    }
+
+    fn exchange_with_bounds_instruction(
+        program_id: Pubkey,
+        open: &OpenEscrow,
+        min_initializer_amount: u64,
+        max_taker_amount: u64,
+    ) -> Instruction {
+        Instruction{
+            program_id,
+            accounts: escrow::accounts::Exchange {
+                taker: open.taker.pubkey(),
+                taker_deposit_token_account: open.taker_b_ata,
+                taker_receive_token_account: open.taker_a_ata,
+                initializer_deposit_token_account: open.initializer_a_ata,
+                initializer_receive_token_account: open.initializer_b_ata,
+                initializer: open.initializer.pubkey(),
+                escrow_account: open.escrow_account,
+                vault_account: open.vault_pda,
+                vault_authority: open.vault_authority,
+                token_program: spl_token::id(),
+                initializer_deposit_mint: open.mint_a,
+                taker_deposit_mint: open.mint_b,
+            }.to_account_metas(None),
+            data: escrow::instruction::ExchangeWithBounds {
+                min_initializer_amount,
+                max_taker_amount,
+            }.data()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exchange_with_bounds_within_limits() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        let exchange_ix = exchange_with_bounds_instruction(pt.program_id, &open, 100, 1000);
+        pt.process_tx_and_assert_ok(&[exchange_ix], &[&open.taker]).await;
+
+        assert_eq!(get_token_balance(open.taker_a_ata, &mut pt).await, 100);
+        assert_eq!(get_token_balance(open.taker_b_ata, &mut pt).await, 0);
+        assert_eq!(get_token_balance(open.initializer_b_ata, &mut pt).await, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_exchange_with_bounds_rejects_initializer_amount_below_minimum() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        let exchange_ix = exchange_with_bounds_instruction(pt.program_id, &open, 101, 1000);
+        pt.process_tx_and_assert_err(
+            &[exchange_ix],
+            &[&open.taker],
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(escrow::EscrowError::InitializerAmountBelowMinimum.into()),
+            ),
+        ).await;
+
+        pt.assert_vault_balance(100).await;
+    }
+
+    #[tokio::test]
+    async fn test_exchange_with_bounds_rejects_taker_amount_above_maximum() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        let exchange_ix = exchange_with_bounds_instruction(pt.program_id, &open, 100, 999);
+        pt.process_tx_and_assert_err(
+            &[exchange_ix],
+            &[&open.taker],
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(escrow::EscrowError::TakerAmountAboveMaximum.into()),
+            ),
+        ).await;
+
+        pt.assert_vault_balance(100).await;
+    }
+
+    #[tokio::test]
+    async fn test_cancel_escrow_returns_deposit() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+        assert_eq!(get_token_balance(open.initializer_a_ata, &mut pt).await, 100);
+
         pt.process_tx_and_assert_ok(&[
             Instruction{
                 program_id: pt.program_id,
-                accounts: escrow::accounts::Exchange { 
-                    taker: escrow_taker_keypair.pubkey(), 
-                    taker_deposit_token_account: taker_b_ata.key(), 
-                    taker_receive_token_account: taker_a_ata.key(), 
-                    initializer_deposit_token_account: initializer_a_ata.key(), 
-                    initializer_receive_token_account: initializer_b_ata.key(), 
-                    initializer: escrow_initializer_keypair.pubkey(), 
-                    escrow_account: escrow_account.pubkey(), 
-                    vault_account: vault_pda, 
-                    vault_authority: vault_authority, 
-                    token_program: spl_token::id() 
+                accounts: escrow::accounts::CancelEscrow {
+                    initializer: open.initializer.pubkey(),
+                    vault_account: open.vault_pda,
+                    vault_authority: open.vault_authority,
+                    initializer_deposit_token_account: open.initializer_a_ata,
+                    escrow_account: open.escrow_account,
+                    token_program: spl_token::id(),
                 }.to_account_metas(None),
-                data: escrow::instruction::Exchange {
+                data: escrow::instruction::CancelEscrow {}.data()
+            }
+        ], &[&open.initializer]).await;
+
+        // the 100 "A" held by the vault are back with the other 100 the initializer kept
+        assert_eq!(get_token_balance(open.initializer_a_ata, &mut pt).await, 200);
+        assert!(pt.context.banks_client.get_account(open.vault_pda).await.unwrap().is_none());
+        assert!(pt.context.banks_client.get_account(open.escrow_account).await.unwrap().is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "is used at both index 0 and index 2")]
+    fn test_assert_distinct_names_the_collision() {
+        let reused = Pubkey::new_unique();
+        assert_distinct(&[reused, Pubkey::new_unique(), reused]);
+    }
+
+    #[tokio::test]
+    async fn test_exchange_emits_exchange_executed_event() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        let exchange_ix = exchange_with_bounds_instruction(pt.program_id, &open, 100, 1000);
+        let logs = pt.process_tx_and_return_logs(&[exchange_ix], &[&open.taker]).await;
+
+        let event_data = logs
+            .iter()
+            .find_map(|log| log.strip_prefix("Program data: "))
+            .expect("no event was logged");
+        let event_bytes = base64::decode(event_data).unwrap();
+        assert_eq!(event_bytes[..8], escrow::ExchangeExecuted::discriminator());
+
+        let event = escrow::ExchangeExecuted::try_from_slice(&event_bytes[8..]).unwrap();
+        assert_eq!(event.initializer, open.initializer.pubkey());
+        assert_eq!(event.taker, open.taker.pubkey());
+        assert_eq!(event.initializer_amount, 100);
+        assert_eq!(event.taker_amount, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_advance_clock_by() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let before = pt.get_clock().await;
+
+        pt.advance_clock_by(3600).await;
+        let after = pt.get_clock().await;
+        assert_eq!(after.unix_timestamp, before.unix_timestamp + 3600);
+        assert!(after.slot >= before.slot + 3600 * 1000 / 400);
+
+        pt.set_unix_timestamp(1_700_000_000).await;
+        assert_eq!(pt.get_clock().await.unix_timestamp, 1_700_000_000);
+    }
+
+    // lamports the payer spends on fees for a plain transfer
+    async fn transfer_fee(pt: &mut EscrowProgramTest) -> u64 {
+        let payer = pt.context.payer.pubkey();
+        let before = get_lamport_balance(payer, pt).await;
+        pt.process_tx_and_assert_ok(&[
+            system_instruction::transfer(&payer, &Pubkey::new_unique(), 1_000_000_000),
+        ], &[]).await;
+        before - get_lamport_balance(payer, pt).await - 1_000_000_000
+    }
+
+    #[tokio::test]
+    async fn test_compute_unit_price_charges_priority_fee() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let base_fee = transfer_fee(&mut pt).await;
+
+        let config = EscrowProgramTestConfig {
+            num_mints: 0,
+            compute_unit_price: 1_000,
+            ..EscrowProgramTestConfig::default()
+        };
+        let mut priced_pt = EscrowProgramTest::start_new_with_config(config).await;
+        let priced_fee = transfer_fee(&mut priced_pt).await;
+
+        assert!(priced_fee > base_fee, "{} is not above {}", priced_fee, base_fee);
+    }
+
+    #[tokio::test]
+    async fn test_start_new_with_two_mints_and_users() {
+        let mut pt = EscrowProgramTest::start_new_with_config(EscrowProgramTestConfig::default_two_mints()).await;
+        pt.create_configured_mints(0).await;
+        assert_eq!(pt.mints.len(), 2);
+        assert_eq!(pt.users.len(), 2);
+
+        assert_ne!(pt.mint(0).pubkey, pt.mint(1).pubkey);
+        assert_eq!(pt.mint(0).unit, 1.0);
+        assert_ne!(pt.user(0).pubkey(), pt.user(1).pubkey());
+    }
+
+    #[tokio::test]
+    async fn test_start_new_funds_users() {
+        let mut pt = EscrowProgramTest::start_new_with_config(EscrowProgramTestConfig::default_two_mints()).await;
+        let user = pt.user(1).pubkey();
+        assert!(get_lamport_balance(user, &mut pt).await >= 1_000_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_tokens() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let sender = Keypair::new();
+        let receiver = Keypair::new();
+        let mint_keypair = Keypair::new();
+        initialize_mint(&mint_keypair, 0, &mut pt).await;
+
+        let sender_ata = initialize_ata(&sender.pubkey(), &mint_keypair.pubkey(), &mut pt).await;
+        let receiver_ata = initialize_ata(&receiver.pubkey(), &mint_keypair.pubkey(), &mut pt).await;
+        mint_some(&sender_ata, &mint_keypair.pubkey(), &mut pt, 100).await;
+
+        pt.transfer_tokens(&sender_ata, &receiver_ata, &sender, 40).await;
+
+        assert_eq!(get_token_balance(sender_ata, &mut pt).await, 60);
+        assert_eq!(get_token_balance(receiver_ata, &mut pt).await, 40);
+    }
+
+    #[tokio::test]
+    async fn test_top_up_escrow() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        pt.process_tx_and_assert_ok(&[
+            Instruction{
+                program_id: pt.program_id,
+                accounts: escrow::accounts::TopUp {
+                    initializer: open.initializer.pubkey(),
+                    vault_account: open.vault_pda,
+                    initializer_deposit_token_account: open.initializer_a_ata,
+                    escrow_account: open.escrow_account,
+                    token_program: spl_token::id(),
+                }.to_account_metas(None),
+                data: escrow::instruction::TopUp {
+                    additional_amount: 50,
                 }.data()
             }
-        ], &[&escrow_taker_keypair]).await;
-        
-        let balance_initializer_a_ata = get_token_balance(initializer_a_ata, &mut pt).await;
-        let balance_initializer_b_ata = get_token_balance(initializer_b_ata, &mut pt).await;
-        let balance_taker_a_ata = get_token_balance(taker_a_ata, &mut pt).await;
-        let balance_taker_b_ata = get_token_balance(taker_b_ata, &mut pt).await;
-        assert!(balance_initializer_a_ata == 100);
-        assert!(balance_initializer_b_ata == 1000);
-        assert!(balance_taker_a_ata == 100);
-        assert!(balance_taker_b_ata == 0);
-        
+        ], &[&open.initializer]).await;
+
+        pt.assert_vault_balance(150).await;
+        assert_eq!(get_token_balance(open.initializer_a_ata, &mut pt).await, 50);
+        let escrow_state: escrow::EscrowAccount = pt.get_account_data(open.escrow_account).await;
+        assert_eq!(escrow_state.initializer_amount, 150);
+    }
+
+    #[tokio::test]
+    async fn test_top_up_rejects_fake_token_program() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        pt.process_tx_and_assert_anchor_err(
+            &[Instruction{
+                program_id: pt.program_id,
+                accounts: escrow::accounts::TopUp {
+                    initializer: open.initializer.pubkey(),
+                    vault_account: open.vault_pda,
+                    initializer_deposit_token_account: open.initializer_a_ata,
+                    escrow_account: open.escrow_account,
+                    token_program: Pubkey::new_unique(),
+                }.to_account_metas(None),
+                data: escrow::instruction::TopUp {
+                    additional_amount: 50,
+                }.data()
+            }],
+            &[&open.initializer],
+            anchor_lang::error::ErrorCode::ConstraintRaw.into(),
+        ).await;
+
+        pt.assert_vault_balance(100).await;
+        assert_eq!(get_token_balance(open.initializer_a_ata, &mut pt).await, 100);
+    }
+
+    #[tokio::test]
+    async fn test_close_token_account_reclaims_rent() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let owner = Keypair::new();
+        let destination = Keypair::new();
+        let mint_keypair = Keypair::new();
+        initialize_mint(&mint_keypair, 0, &mut pt).await;
+        airdrop(&destination.pubkey(), 0, &mut pt).await;
+
+        let owner_ata = initialize_ata(&owner.pubkey(), &mint_keypair.pubkey(), &mut pt).await;
+        let destination_before = get_lamport_balance(destination.pubkey(), &mut pt).await;
+
+        pt.close_token_account(&owner_ata, &destination.pubkey(), &owner).await.unwrap();
+
+        let reclaimed = get_lamport_balance(destination.pubkey(), &mut pt).await - destination_before;
+        assert_eq!(reclaimed, pt.rent.minimum_balance(spl_token::state::Account::LEN));
+        assert!(pt.context.banks_client.get_account(owner_ata).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_close_token_account_with_tokens_fails() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let owner = Keypair::new();
+        let mint_keypair = Keypair::new();
+        initialize_mint(&mint_keypair, 0, &mut pt).await;
+
+        let owner_ata = initialize_ata(&owner.pubkey(), &mint_keypair.pubkey(), &mut pt).await;
+        mint_some(&owner_ata, &mint_keypair.pubkey(), &mut pt, 1).await;
+
+        let payer = pt.context.payer.pubkey();
+        let err = pt.close_token_account(&owner_ata, &payer, &owner).await.unwrap_err();
+        assert!(err.contains("still holds 1 tokens"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_get_mint_supply_and_decimals() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let user = Keypair::new();
+        let mint_keypair = Keypair::new();
+        initialize_mint(&mint_keypair, 6, &mut pt).await;
+
+        let user_ata = initialize_ata(&user.pubkey(), &mint_keypair.pubkey(), &mut pt).await;
+        mint_some(&user_ata, &mint_keypair.pubkey(), &mut pt, 1000).await;
+
+        assert_eq!(pt.get_mint_supply(mint_keypair.pubkey()).await, 1000);
+        assert_eq!(pt.get_mint_decimals(mint_keypair.pubkey()).await, 6);
+    }
+
+    #[tokio::test]
+    async fn test_assert_rent_paying_below_threshold() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let pubkey = Pubkey::new_unique();
+        let threshold = pt.rent.minimum_balance(100);
+
+        let mut account = AccountSharedData::new(threshold, 100, &pt.program_id);
+        pt.context.set_account(&pubkey, &account);
+        pt.assert_rent_exempt(pubkey).await;
+
+        // drain a single lamport below the exemption threshold
+        account.set_lamports(threshold - 1);
+        pt.context.set_account(&pubkey, &account);
+        pt.assert_rent_paying(pubkey).await;
+    }
+
+    #[tokio::test]
+    async fn test_exchange_with_too_few_taker_tokens_fails_with_anchor_error() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        // the taker is one "B" short of the 1000 the escrow asks for
+        pt.transfer_tokens(&open.taker_b_ata, &open.initializer_b_ata, &open.taker, 1).await;
+
+        pt.process_tx_and_assert_anchor_err(
+            &[Instruction{
+                program_id: pt.program_id,
+                accounts: escrow::accounts::Exchange {
+                    taker: open.taker.pubkey(),
+                    taker_deposit_token_account: open.taker_b_ata,
+                    taker_receive_token_account: open.taker_a_ata,
+                    initializer_deposit_token_account: open.initializer_a_ata,
+                    initializer_receive_token_account: open.initializer_b_ata,
+                    initializer: open.initializer.pubkey(),
+                    escrow_account: open.escrow_account,
+                    vault_account: open.vault_pda,
+                    vault_authority: open.vault_authority,
+                    token_program: spl_token::id(),
+                    initializer_deposit_mint: open.mint_a,
+                    taker_deposit_mint: open.mint_b,
+                }.to_account_metas(None),
+                data: escrow::instruction::Exchange { max_taker_amount: 1000 }.data()
+            }],
+            &[&open.taker],
+            anchor_lang::error::ErrorCode::ConstraintRaw.into(),
+        ).await;
+
+        pt.assert_vault_balance(100).await;
+    }
+
+    #[tokio::test]
+    async fn test_exchange_rejects_taker_amount_above_slippage_limit() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        // the escrow asks for 1000 "B", the taker won't pay more than 999
+        pt.process_tx_and_assert_anchor_err(
+            &[Instruction{
+                program_id: pt.program_id,
+                accounts: escrow::accounts::Exchange {
+                    taker: open.taker.pubkey(),
+                    taker_deposit_token_account: open.taker_b_ata,
+                    taker_receive_token_account: open.taker_a_ata,
+                    initializer_deposit_token_account: open.initializer_a_ata,
+                    initializer_receive_token_account: open.initializer_b_ata,
+                    initializer: open.initializer.pubkey(),
+                    escrow_account: open.escrow_account,
+                    vault_account: open.vault_pda,
+                    vault_authority: open.vault_authority,
+                    token_program: spl_token::id(),
+                    initializer_deposit_mint: open.mint_a,
+                    taker_deposit_mint: open.mint_b,
+                }.to_account_metas(None),
+                data: escrow::instruction::Exchange { max_taker_amount: 999 }.data()
+            }],
+            &[&open.taker],
+            escrow::EscrowError::SlippageExceeded.into(),
+        ).await;
+
+        pt.assert_vault_balance(100).await;
+        assert_eq!(get_token_balance(open.taker_b_ata, &mut pt).await, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_exchange_with_swapped_mints_is_rejected_by_token_program() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        // transfer_checked compares the passed mint with the token accounts' mint
+        pt.process_tx_and_assert_err(
+            &[Instruction{
+                program_id: pt.program_id,
+                accounts: escrow::accounts::Exchange {
+                    taker: open.taker.pubkey(),
+                    taker_deposit_token_account: open.taker_b_ata,
+                    taker_receive_token_account: open.taker_a_ata,
+                    initializer_deposit_token_account: open.initializer_a_ata,
+                    initializer_receive_token_account: open.initializer_b_ata,
+                    initializer: open.initializer.pubkey(),
+                    escrow_account: open.escrow_account,
+                    vault_account: open.vault_pda,
+                    vault_authority: open.vault_authority,
+                    token_program: spl_token::id(),
+                    initializer_deposit_mint: open.mint_b,
+                    taker_deposit_mint: open.mint_a,
+                }.to_account_metas(None),
+                data: escrow::instruction::Exchange { max_taker_amount: 1000 }.data()
+            }],
+            &[&open.taker],
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(spl_token::error::TokenError::MintMismatch as u32),
+            ),
+        ).await;
+
+        pt.assert_vault_balance(100).await;
+        assert_eq!(get_token_balance(open.taker_b_ata, &mut pt).await, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_escrow_account_discriminator() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        pt.assert_discriminator(open.escrow_account, escrow_discriminator()).await;
+    }
+
+    #[tokio::test]
+    async fn test_get_account_optional_of_missing_account() {
+        let mut pt = EscrowProgramTest::start_new().await;
+
+        assert!(pt.get_account_optional(Pubkey::new_unique()).await.is_none());
+        let payer = pt.context.payer.pubkey();
+        assert!(pt.get_account_optional(payer).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_airdrop_exact() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let receiver = Pubkey::new_unique();
+
+        airdrop_exact(&receiver, 5_000_000, &mut pt).await;
+
+        assert_eq!(get_lamport_balance(receiver, &mut pt).await, 5_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_utils_airdrop_has_no_rent_padding() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let exact = Pubkey::new_unique();
+        let padded = Pubkey::new_unique();
+
+        program_test::utils::airdrop(&mut pt.context, &exact, 5_000_000).await;
+        airdrop(&padded, 5_000_000, &mut pt).await;
+
+        assert_eq!(get_lamport_balance(exact, &mut pt).await, 5_000_000);
+        let padding = pt.rent.minimum_balance(size_of::<solana_sdk::account::Account>());
+        assert_eq!(get_lamport_balance(padded, &mut pt).await, 5_000_000 + padding);
+    }
+
+    #[tokio::test]
+    async fn test_compute_limit_is_enforced() {
+        let config = EscrowProgramTestConfig::default()
+            .with_num_mints(0)
+            .with_num_users(0)
+            .with_compute_limit(1_000);
+        let mut pt = EscrowProgramTest::start_new_with_config(config).await;
+
+        // a plain transfer fits in the limit
+        let receiver = Pubkey::new_unique();
+        pt.process_tx_and_assert_ok(
+            &[system_instruction::transfer(&pt.context.payer.pubkey(), &receiver, 5_000_000)],
+            &[],
+        ).await;
+
+        // initializing a mint is fine on its own but needs more than 1_000 units
+        let mint = Keypair::new();
+        let tx = pt.sign_tx(
+            &[
+                system_instruction::create_account(
+                    &pt.context.payer.pubkey(),
+                    &mint.pubkey(),
+                    pt.rent.minimum_balance(spl_token::state::Mint::LEN),
+                    spl_token::state::Mint::LEN as u64,
+                    &spl_token::id(),
+                ),
+                spl_token::instruction::initialize_mint(
+                    &spl_token::id(),
+                    &mint.pubkey(),
+                    &pt.context.payer.pubkey(),
+                    None,
+                    0,
+                ).unwrap(),
+            ],
+            &[&mint],
+        );
+        let err = pt.context.banks_client.process_transaction(tx).await.unwrap_err().unwrap();
+        assert!(
+            // running out of units inside a loaded program is reported as the program failing to complete
+            matches!(err, TransactionError::InstructionError(_, InstructionError::ProgramFailedToComplete)),
+            "{:?}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_process_tx_returns_raw_error() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        let exchange_ix = exchange_with_bounds_instruction(pt.program_id, &open, 101, 1000);
+        match pt.process_tx(&[exchange_ix], &[&open.taker]).await {
+            // the failed preflight simulation comes back together with the program logs
+            Err(BanksClientError::SimulationError { err, logs, .. }) => {
+                assert_eq!(
+                    err,
+                    TransactionError::InstructionError(
+                        0,
+                        InstructionError::Custom(escrow::EscrowError::InitializerAmountBelowMinimum.into()),
+                    )
+                );
+                assert!(
+                    logs.iter().any(|log| log.contains("InitializerAmountBelowMinimum")),
+                    "{:#?}",
+                    logs
+                );
+            }
+            other => panic!("expected a simulation error, got {:?}", other),
+        }
+
+        pt.assert_vault_balance(100).await;
     }
-}
\ No newline at end of file
+}
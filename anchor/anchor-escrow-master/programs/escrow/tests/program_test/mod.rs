@@ -1,11 +1,14 @@
 use anchor_lang::solana_program::program_pack::Pack;
-use anchor_lang::{prelude::*, InstructionData};
+use anchor_lang::solana_program::program_stubs::{self, SyscallStubs};
+use anchor_lang::{prelude::*, Discriminator, InstructionData};
 use assert_matches::assert_matches;
 use bincode::deserialize;
-use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
 use solana_sdk::account::ReadableAccount;
 use solana_sdk::{
-    instruction::{Instruction},
+    clock::DEFAULT_MS_PER_SLOT,
+    compute_budget::ComputeBudgetInstruction,
+    instruction::{Instruction, InstructionError},
     pubkey::Pubkey,
     signature::{Keypair, Signer},
     system_instruction, sysvar,
@@ -13,11 +16,19 @@ use solana_sdk::{
     account::Account,
 };
 use std::mem::size_of;
+use std::sync::Once;
 
+pub mod utils;
+
+#[derive(Clone, Debug)]
 pub struct EscrowProgramTestConfig {
     pub compute_limit: u64,
     pub num_users: usize,
     pub num_mints: usize,
+    // micro-lamports per compute unit, 0 leaves transactions without a priority fee
+    pub compute_unit_price: u64,
+    // decimals of each mint by index, mints without an entry get create_configured_mints' decimals
+    pub mint_decimals: Vec<u8>,
 }
 
 impl EscrowProgramTestConfig {
@@ -26,13 +37,27 @@ impl EscrowProgramTestConfig {
         EscrowProgramTestConfig {
             compute_limit: 200_000,
             num_users: 2,
-            num_mints: 16
+            num_mints: 16,
+            compute_unit_price: 0,
+            mint_decimals: vec![],
         }
     }
     #[allow(dead_code)]
     pub fn default_two_mints() -> Self {
         EscrowProgramTestConfig { num_mints: 2, ..Self::default() }
     }
+    #[allow(dead_code)]
+    pub fn with_compute_limit(self, compute_limit: u64) -> Self {
+        EscrowProgramTestConfig { compute_limit, ..self }
+    }
+    #[allow(dead_code)]
+    pub fn with_num_users(self, num_users: usize) -> Self {
+        EscrowProgramTestConfig { num_users, ..self }
+    }
+    #[allow(dead_code)]
+    pub fn with_num_mints(self, num_mints: usize) -> Self {
+        EscrowProgramTestConfig { num_mints, ..self }
+    }
 }
 
 pub trait AddPacked {
@@ -58,53 +83,270 @@ impl AddPacked for ProgramTest {
         self.add_account(pubkey, account);
     }
 }
+#[derive(Clone, Copy, Debug)]
+pub struct MintCookie {
+    pub pubkey: Pubkey,
+    pub decimals: u8,
+    // one whole token in base units, 10^decimals
+    pub unit: f64,
+}
+
+// two fresh mints with both parties' ATAs, the initializer holding 200 "A" and the taker 1000 "B"
+#[derive(Clone, Copy, Debug)]
+pub struct TwoPartySetup {
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub initializer_a_ata: Pubkey,
+    pub initializer_b_ata: Pubkey,
+    pub taker_a_ata: Pubkey,
+    pub taker_b_ata: Pubkey,
+}
+
 pub struct EscrowProgramTest {
     pub context: ProgramTestContext,
     pub rent: Rent,
     pub program_id: Pubkey,
-    // pub num_mints: usize,
+    pub mints: Vec<MintCookie>,
+    pub users: Vec<Keypair>,
+    // the config's num_mints and mint_decimals, created by create_configured_mints
+    pub num_mints: usize,
+    pub mint_decimals: Vec<u8>,
+    pub compute_unit_price: u64,
+    // compute units per transaction, 0 keeps the runtime's default limit
+    pub compute_limit: u64,
     // pub quote_index: usize,
     // pub quote_mint: MintCookie,
-    // pub mints: Vec<MintCookie>,
-    // pub num_users: usize,
-    // pub users: Vec<Keypair>,
     // pub vaults: Vec<Pubkey>,
     // pub vault_bumps: Vec<u8>,
     // pub token_accounts: Vec<Pubkey>, // user x mint
 }
 
+// anchor's entry wants the accounts to live as long as their data, which the
+// program-test processor signature can't promise, so the slice is leaked for the test
+fn process_instruction(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> anchor_lang::solana_program::entrypoint::ProgramResult {
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+    escrow::entry(program_id, accounts, data)
+}
+
+// program-test's stubs only print sol_log_data to stdout for natively built programs, so emit!
+// never reaches the transaction logs; this forwards everything else to them and sends the
+// event through sol_log instead, where it shows up as "Program log: Program data: <base64>"
+struct EventLoggingStubs(Box<dyn SyscallStubs>);
+
+impl SyscallStubs for EventLoggingStubs {
+    fn sol_log(&self, message: &str) {
+        self.0.sol_log(message)
+    }
+    fn sol_log_compute_units(&self) {
+        self.0.sol_log_compute_units()
+    }
+    fn sol_remaining_compute_units(&self) -> u64 {
+        self.0.sol_remaining_compute_units()
+    }
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> anchor_lang::solana_program::entrypoint::ProgramResult {
+        self.0.sol_invoke_signed(instruction, account_infos, signers_seeds)
+    }
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_clock_sysvar(var_addr)
+    }
+    fn sol_get_epoch_schedule_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_epoch_schedule_sysvar(var_addr)
+    }
+    fn sol_get_fees_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_fees_sysvar(var_addr)
+    }
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_rent_sysvar(var_addr)
+    }
+    fn sol_get_epoch_rewards_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_epoch_rewards_sysvar(var_addr)
+    }
+    fn sol_get_last_restart_slot(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_last_restart_slot(var_addr)
+    }
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        self.0.sol_get_return_data()
+    }
+    fn sol_set_return_data(&self, data: &[u8]) {
+        self.0.sol_set_return_data(data)
+    }
+    fn sol_log_data(&self, fields: &[&[u8]]) {
+        let fields: Vec<String> = fields.iter().map(base64::encode).collect();
+        self.0.sol_log(&format!("Program data: {}", fields.join(" ")));
+    }
+    fn sol_get_processed_sibling_instruction(&self, index: usize) -> Option<Instruction> {
+        self.0.sol_get_processed_sibling_instruction(index)
+    }
+    fn sol_get_stack_height(&self) -> u64 {
+        self.0.sol_get_stack_height()
+    }
+}
+
+// stands in for a moment while the program-test stubs are swapped out for the wrapper
+struct NoStubs;
+
+impl SyscallStubs for NoStubs {}
+
+static EVENT_LOGGING_STUBS: Once = Once::new();
+
 impl EscrowProgramTest {
     pub async fn start_new() -> Self {
-        let pt = ProgramTest::new("escrow", escrow::ID, processor!(escrow::entry));
+        let pt = ProgramTest::new("escrow", escrow::ID, processor!(process_instruction));
         let mut context = pt.start_with_context().await;
+        // program-test only installs its own stubs on the first start, so they get wrapped once too
+        EVENT_LOGGING_STUBS.call_once(|| {
+            let program_test_stubs = program_stubs::set_syscall_stubs(Box::new(NoStubs));
+            program_stubs::set_syscall_stubs(Box::new(EventLoggingStubs(program_test_stubs)));
+        });
         let rent = context.banks_client.get_rent().await.unwrap();
 
         Self {
             context,
             rent,
             program_id: escrow::ID,
+            mints: vec![],
+            users: vec![],
+            num_mints: 0,
+            mint_decimals: vec![],
+            compute_unit_price: 0,
+            compute_limit: 0,
         }
     }
 
-    pub async fn process_tx_and_assert_ok(
-        &mut self,
-        instructions: &[Instruction],
-        signers: &[&Keypair],
-    ) {
+    pub async fn start_new_with_config(config: EscrowProgramTestConfig) -> Self {
+        let mut escrow_program_test = Self::start_new().await;
+        escrow_program_test.compute_unit_price = config.compute_unit_price;
+        escrow_program_test.compute_limit = config.compute_limit;
+        escrow_program_test.num_mints = config.num_mints;
+        escrow_program_test.mint_decimals = config.mint_decimals;
+
+        for _ in 0..config.num_users {
+            let user = Keypair::new();
+            airdrop(&user.pubkey(), 1_000_000_000, &mut escrow_program_test).await;
+            escrow_program_test.users.push(user);
+        }
+
+        escrow_program_test
+    }
+
+    // the token account holding the initializer's deposit, seeded with b"token-seed"
+    pub fn vault_pda(&self) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"token-seed".as_ref()], &self.program_id)
+    }
+
+    // the PDA the vault is handed over to, seeded with b"escrow"
+    pub fn vault_authority(&self) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"escrow".as_ref()], &self.program_id)
+    }
+
+    // the config's num_mints mints, `decimals` for the ones without an entry in mint_decimals
+    pub async fn create_configured_mints(&mut self, decimals: u8) -> Vec<Pubkey> {
+        assert!(self.mints.is_empty(), "the configured mints were already created");
+        let mut pubkeys = Vec::with_capacity(self.num_mints);
+        for i in 0..self.num_mints {
+            let decimals = self.mint_decimals.get(i).copied().unwrap_or(decimals);
+            pubkeys.push(self.add_mint(decimals).await);
+        }
+        pubkeys
+    }
+
+    async fn add_mint(&mut self, decimals: u8) -> Pubkey {
+        let mint_keypair = Keypair::new();
+        initialize_mint(&mint_keypair, decimals, self).await;
+        self.mints.push(MintCookie {
+            pubkey: mint_keypair.pubkey(),
+            decimals,
+            unit: 10f64.powi(decimals as i32),
+        });
+        mint_keypair.pubkey()
+    }
+
+    pub fn mint(&self, i: usize) -> &MintCookie {
+        &self.mints[i]
+    }
+
+    pub fn user(&self, i: usize) -> &Keypair {
+        &self.users[i]
+    }
+
+    // signs with the payer first, prepending the compute limit and priority fee when they are configured
+    pub fn sign_tx(&self, instructions: &[Instruction], signers: &[&Keypair]) -> Transaction {
+        let mut all_instructions = vec![];
+        if self.compute_limit > 0 {
+            all_instructions.push(ComputeBudgetInstruction::set_compute_unit_limit(
+                self.compute_limit as u32,
+            ));
+        }
+        if self.compute_unit_price > 0 {
+            all_instructions.push(ComputeBudgetInstruction::set_compute_unit_price(
+                self.compute_unit_price,
+            ));
+        }
+        all_instructions.extend_from_slice(instructions);
+
         let mut all_signers = vec![&self.context.payer];
         all_signers.extend_from_slice(signers);
 
-        let tx = Transaction::new_signed_with_payer(
-            &instructions,
+        Transaction::new_signed_with_payer(
+            &all_instructions,
             Some(&self.context.payer.pubkey()),
             &all_signers,
             self.context.last_blockhash,
-        );
+        )
+    }
 
-        assert_matches!(
-            self.context.banks_client.process_transaction(tx).await,
-            Ok(())
-        );
+    // processes the transaction and hands back the raw result, for tests that inspect the failure themselves
+    pub async fn process_tx(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> std::result::Result<(), BanksClientError> {
+        let tx = self.sign_tx(instructions, signers);
+        // process_transaction stopped simulating first, the preflight keeps failures coming
+        // back as simulation errors with their logs
+        self.context.banks_client.process_transaction_with_preflight(tx).await
+    }
+
+    pub async fn process_tx_and_assert_ok(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) {
+        assert_matches!(self.process_tx(instructions, signers).await, Ok(()));
+    }
+
+    // processes the transaction, asserts it succeeded and returns the program logs it emitted
+    pub async fn process_tx_and_return_logs(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Vec<String> {
+        let tx = self.sign_tx(instructions, signers);
+
+        // the banks client only hands out logs for simulations, so the transaction is simulated
+        // first and then sent for real
+        let simulation = self
+            .context
+            .banks_client
+            .simulate_transaction(tx.clone())
+            .await
+            .unwrap();
+        let logs: Vec<String> = simulation
+            .simulation_details
+            .expect("simulation details")
+            .logs
+            .into_iter()
+            // events logged through EventLoggingStubs, put back into the validator's shape
+            .map(|log| log.replacen("Program log: Program data: ", "Program data: ", 1))
+            .collect();
+        assert_matches!(simulation.result, Some(Ok(())), "{:?}", logs);
+        assert_matches!(self.context.banks_client.process_transaction(tx).await, Ok(()));
+        logs
     }
 
     pub async fn process_tx_and_assert_err(
@@ -113,27 +355,276 @@ impl EscrowProgramTest {
         signers: &[&Keypair],
         transaction_error: TransactionError,
     ) {
-        let mut all_signers = vec![&self.context.payer];
-        all_signers.extend_from_slice(signers);
+        assert_eq!(
+            transaction_error,
+            self.process_tx(instructions, signers).await.unwrap_err().unwrap(),
+        );
+    }
 
-        let tx = Transaction::new_signed_with_payer(
-            &instructions,
-            Some(&self.context.payer.pubkey()),
-            &all_signers,
-            self.context.last_blockhash,
+    pub async fn transfer_tokens(
+        &mut self,
+        source: &Pubkey,
+        dest: &Pubkey,
+        authority: &Keypair,
+        amount: u64,
+    ) {
+        self.process_tx_and_assert_ok(
+            &[spl_token::instruction::transfer(
+                &spl_token::id(),
+                source,
+                dest,
+                &authority.pubkey(),
+                &[],
+                amount,
+            )
+            .unwrap()],
+            &[authority],
+        )
+        .await;
+    }
+
+    // the payer is the mint authority, recipients are token accounts of `mint`,
+    // batched so each transaction stays under the packet size limit
+    pub async fn mint_to_many(&mut self, mint: &Pubkey, recipients: &[(Pubkey, u64)]) {
+        const RECIPIENTS_PER_TX: usize = 16;
+        for batch in recipients.chunks(RECIPIENTS_PER_TX) {
+            let instructions: Vec<Instruction> = batch
+                .iter()
+                .map(|(token_account, amount)| {
+                    spl_token::instruction::mint_to(
+                        &spl_token::id(),
+                        mint,
+                        token_account,
+                        &self.context.payer.pubkey(),
+                        &[],
+                        *amount,
+                    )
+                    .unwrap()
+                })
+                .collect();
+            self.process_tx_and_assert_ok(&instructions, &[]).await;
+        }
+    }
+
+    // closing only works on an empty token account, so that case is reported instead of failing the tx
+    pub async fn close_token_account(
+        &mut self,
+        account: &Pubkey,
+        destination: &Pubkey,
+        authority: &Keypair,
+    ) -> std::result::Result<(), String> {
+        let balance = get_token_balance(*account, self).await;
+        if balance != 0 {
+            return Err(format!(
+                "token account {} still holds {} tokens and cannot be closed",
+                account, balance
+            ));
+        }
+
+        self.process_tx_and_assert_ok(
+            &[spl_token::instruction::close_account(
+                &spl_token::id(),
+                account,
+                destination,
+                &authority.pubkey(),
+                &[],
+            )
+            .unwrap()],
+            &[authority],
+        )
+        .await;
+        Ok(())
+    }
+
+    pub async fn get_mint_supply(&mut self, mint: Pubkey) -> u64 {
+        self.get_mint(mint).await.supply
+    }
+
+    pub async fn get_mint_decimals(&mut self, mint: Pubkey) -> u8 {
+        self.get_mint(mint).await.decimals
+    }
+
+    // None when the account doesn't exist, e.g. after it was closed
+    pub async fn get_account_optional(&mut self, pubkey: Pubkey) -> Option<Account> {
+        self.context.banks_client.get_account(pubkey).await.unwrap()
+    }
+
+    async fn get_mint(&mut self, mint: Pubkey) -> spl_token::state::Mint {
+        let mint_account = self
+            .context
+            .banks_client
+            .get_account(mint)
+            .await
+            .unwrap()
+            .unwrap_or_else(|| panic!("mint {} does not exist", mint));
+        spl_token::state::Mint::unpack(&mint_account.data).unwrap()
+    }
+
+    pub async fn assert_rent_exempt(&mut self, pubkey: Pubkey) {
+        let account = self
+            .get_account_optional(pubkey)
+            .await
+            .unwrap_or_else(|| panic!("account {} not found", pubkey));
+        assert!(
+            self.rent.is_exempt(account.lamports, account.data.len()),
+            "account {} holds {} lamports and is not rent exempt",
+            pubkey,
+            account.lamports
         );
+    }
 
-        assert_eq!(
+    pub async fn assert_rent_paying(&mut self, pubkey: Pubkey) {
+        let account = self
+            .get_account_optional(pubkey)
+            .await
+            .unwrap_or_else(|| panic!("account {} not found", pubkey));
+        assert!(
+            !self.rent.is_exempt(account.lamports, account.data.len()),
+            "account {} holds {} lamports and is still rent exempt",
+            pubkey,
+            account.lamports
+        );
+    }
+
+    // for anchor's ProgramError::Custom codes, e.g. u32::from(escrow::EscrowError::AmountOverflow)
+    pub async fn process_tx_and_assert_anchor_err(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+        error_code: u32,
+    ) {
+        let transaction_error = self.process_tx(instructions, signers).await.unwrap_err().unwrap();
+        assert_matches!(
             transaction_error,
-            self.context
-                .banks_client
-                .process_transaction(tx)
-                .await
-                .unwrap_err()
-                .unwrap(),
+            TransactionError::InstructionError(_, InstructionError::Custom(code)) if code == error_code,
+            "expected custom error {}",
+            error_code
+        );
+    }
+
+    // creates the escrow account and submits InitializeEscrow, the initializer pays for the account
+    pub async fn create_escrow(
+        &mut self,
+        initializer: &Keypair,
+        mint: &Pubkey,
+        deposit_ata: &Pubkey,
+        receive_ata: &Pubkey,
+        initializer_amount: u64,
+        taker_amount: u64,
+    ) -> (Pubkey, Pubkey) {
+        let escrow_account = Keypair::new();
+        let escrow_len = 8 + size_of::<escrow::EscrowAccount>();
+        let (vault_pda, vault_bump) = self.vault_pda();
+
+        self.process_tx_and_assert_ok(
+            &[
+                system_instruction::create_account(
+                    &initializer.pubkey(),
+                    &escrow_account.pubkey(),
+                    self.rent.minimum_balance(escrow_len),
+                    escrow_len as u64,
+                    &self.program_id,
+                ),
+                Instruction {
+                    program_id: self.program_id,
+                    accounts: escrow::accounts::InitializeEscrow {
+                        initializer: initializer.pubkey(),
+                        mint: *mint,
+                        vault_account: vault_pda,
+                        initializer_deposit_token_account: *deposit_ata,
+                        initializer_receive_token_account: *receive_ata,
+                        escrow_account: escrow_account.pubkey(),
+                        system_program: solana_sdk::system_program::id(),
+                        rent: sysvar::rent::ID,
+                        token_program: spl_token::id(),
+                    }
+                    .to_account_metas(None),
+                    data: escrow::instruction::InitializeEscrow {
+                        _vault_account_bump: vault_bump,
+                        initializer_amount,
+                        taker_amount,
+                    }
+                    .data(),
+                },
+            ],
+            &[initializer, &escrow_account],
+        )
+        .await;
+
+        (escrow_account.pubkey(), vault_pda)
+    }
+
+    pub async fn setup_two_party_mints(&mut self, initializer: &Keypair, taker: &Keypair) -> TwoPartySetup {
+        let mint_a_keypair = Keypair::new();
+        let mint_b_keypair = Keypair::new();
+        let mint_a = mint_a_keypair.pubkey();
+        let mint_b = mint_b_keypair.pubkey();
+
+        initialize_mint(&mint_a_keypair, 0, self).await;
+        let initializer_a_ata = initialize_ata(&initializer.pubkey(), &mint_a, self).await;
+        let taker_a_ata = initialize_ata(&taker.pubkey(), &mint_a, self).await;
+        mint_some(&initializer_a_ata, &mint_a, self, 200).await;
+
+        initialize_mint(&mint_b_keypair, 3, self).await;
+        let initializer_b_ata = initialize_ata(&initializer.pubkey(), &mint_b, self).await;
+        let taker_b_ata = initialize_ata(&taker.pubkey(), &mint_b, self).await;
+        mint_some(&taker_b_ata, &mint_b, self, 1000).await;
+
+        TwoPartySetup {
+            mint_a,
+            mint_b,
+            initializer_a_ata,
+            initializer_b_ata,
+            taker_a_ata,
+            taker_b_ata,
+        }
+    }
+
+    pub async fn assert_token_balance(&mut self, account: Pubkey, expected: u64) {
+        let actual = get_token_balance(account, self).await;
+        assert_eq!(
+            actual, expected,
+            "token account {} holds {}, expected {}",
+            account, actual, expected
         );
     }
 
+    // the vault is a single PDA seeded with b"token-seed", it doesn't depend on the escrow account
+    pub async fn assert_vault_balance(&mut self, expected: u64) {
+        let (vault_pda, _vault_bump) = self.vault_pda();
+        let balance = get_token_balance(vault_pda, self).await;
+        assert_eq!(balance, expected, "unexpected balance in vault {}", vault_pda);
+    }
+
+    pub async fn assert_discriminator(&mut self, pubkey: Pubkey, expected: [u8; 8]) {
+        let account = self
+            .context
+            .banks_client
+            .get_account(pubkey)
+            .await
+            .unwrap()
+            .unwrap_or_else(|| panic!("account {} does not exist", pubkey));
+        assert_eq!(
+            account.data.get(..8),
+            Some(&expected[..]),
+            "unexpected discriminator for account {}",
+            pubkey
+        );
+    }
+
+    // reads a program account, skipping the 8 byte anchor discriminator
+    pub async fn get_account_data<T: AnchorDeserialize>(&mut self, pubkey: Pubkey) -> T {
+        let account = self
+            .context
+            .banks_client
+            .get_account(pubkey)
+            .await
+            .unwrap()
+            .unwrap_or_else(|| panic!("account {} does not exist", pubkey));
+        T::deserialize(&mut &account.data[8..])
+            .unwrap_or_else(|err| panic!("failed to deserialize account {}: {}", pubkey, err))
+    }
+
     pub async fn get_clock(&mut self) -> Clock {
         deserialize::<Clock>(
             &self
@@ -147,6 +638,22 @@ impl EscrowProgramTest {
         )
         .unwrap()
     }
+
+    // moves the bank forward by the slots `seconds` would take (~400ms each), then pins the
+    // clock to exactly `seconds` later since a bank without votes only estimates the timestamp
+    pub async fn advance_clock_by(&mut self, seconds: i64) {
+        let clock = self.get_clock().await;
+        let slots = (seconds.max(0) as u64 * 1000 / DEFAULT_MS_PER_SLOT).max(1);
+        self.context.warp_to_slot(clock.slot + slots).unwrap();
+        self.context.last_blockhash = self.context.banks_client.get_latest_blockhash().await.unwrap();
+        self.set_unix_timestamp(clock.unix_timestamp + seconds).await;
+    }
+
+    pub async fn set_unix_timestamp(&mut self, unix_timestamp: i64) {
+        let mut clock = self.get_clock().await;
+        clock.unix_timestamp = unix_timestamp;
+        self.context.set_sysvar(&clock);
+    }
 }
 
 pub async fn initialize_mint(
@@ -186,6 +693,30 @@ pub async fn initialize_mint(
         .await;
 }
 
+// the 8 bytes anchor prefixes every EscrowAccount with
+pub fn escrow_discriminator() -> [u8; 8] {
+    escrow::EscrowAccount::discriminator()
+}
+
+// catches a keypair accidentally reused for two different roles in a test setup
+pub fn assert_distinct(pubkeys: &[Pubkey]) {
+    for (i, pubkey) in pubkeys.iter().enumerate() {
+        if let Some(j) = pubkeys[i + 1..].iter().position(|other| other == pubkey) {
+            panic!(
+                "pubkey {} is used at both index {} and index {}",
+                pubkey,
+                i,
+                i + 1 + j
+            );
+        }
+    }
+}
+
+// predicts the ATA address without creating it
+pub fn ata(user: &Pubkey, mint: &Pubkey) -> Pubkey {
+    spl_associated_token_account::get_associated_token_address(user, mint)
+}
+
 pub async fn initialize_ata(
     user: &Pubkey,
     mint: &Pubkey,
@@ -203,7 +734,7 @@ pub async fn initialize_ata(
             &[],
         )
         .await;
-    spl_associated_token_account::get_associated_token_address(user, mint)
+    ata(user, mint)
 }
 
 // To simplify, the payer is mint authority of all mints
@@ -230,15 +761,22 @@ pub async fn mint_some(
 }
 
 pub async fn get_token_balance(pubkey: Pubkey, escrow_program_test: &mut EscrowProgramTest) -> u64 {
-    let token: Account = escrow_program_test.context.banks_client.get_account(pubkey).await.unwrap().unwrap();
+    let token: Account = escrow_program_test
+        .get_account_optional(pubkey)
+        .await
+        .unwrap_or_else(|| panic!("account {} not found", pubkey));
 
     spl_token::state::Account::unpack(&token.data[..])
-        .unwrap()
+        .unwrap_or_else(|err| panic!("account {} is not a token account: {}", pubkey, err))
         .amount
 }
 
 pub async fn get_lamport_balance(address: Pubkey, escrow_program_test: &mut EscrowProgramTest) -> u64 {
-    escrow_program_test.context.banks_client.get_account(address).await.unwrap().unwrap().lamports()
+    escrow_program_test
+        .get_account_optional(address)
+        .await
+        .unwrap_or_else(|| panic!("account {} not found", address))
+        .lamports()
 }
 
 pub async fn get_rent_minimum_balance(len: usize, escrow_program_test: &mut EscrowProgramTest) -> u64 {
@@ -261,16 +799,10 @@ pub async fn airdrop(receiver: &Pubkey, amount: u64, escrow_program_test: &mut E
         .unwrap()
         .minimum_balance(size_of::<Account>());
 
-    let tx = Transaction::new_signed_with_payer(
-        &[system_instruction::transfer(
-            &escrow_program_test.context.payer.pubkey(),
-            receiver,
-            rent_exempt_threshold + amount,
-        )],
-        Some(&escrow_program_test.context.payer.pubkey()),
-        &[&escrow_program_test.context.payer],
-        escrow_program_test.context.last_blockhash,
-    );
+    utils::airdrop(&mut escrow_program_test.context, receiver, rent_exempt_threshold + amount).await;
+}
 
-    escrow_program_test.context.banks_client.process_transaction(tx).await.unwrap();
+// transfers exactly `lamports`, without the rent padding `airdrop` adds
+pub async fn airdrop_exact(receiver: &Pubkey, lamports: u64, escrow_program_test: &mut EscrowProgramTest) {
+    utils::airdrop(&mut escrow_program_test.context, receiver, lamports).await;
 }
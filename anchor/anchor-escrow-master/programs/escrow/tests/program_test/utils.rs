@@ -0,0 +1,23 @@
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::Signer,
+    system_instruction,
+    transaction::Transaction,
+};
+
+// transfers exactly `lamports` from the payer, the harness airdrops are built on top of this
+pub async fn airdrop(context: &mut ProgramTestContext, receiver: &Pubkey, lamports: u64) {
+    let tx = Transaction::new_signed_with_payer(
+        &[system_instruction::transfer(
+            &context.payer.pubkey(),
+            receiver,
+            lamports,
+        )],
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        context.last_blockhash,
+    );
+
+    context.banks_client.process_transaction(tx).await.unwrap();
+}
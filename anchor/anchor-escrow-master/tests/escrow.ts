@@ -164,7 +164,7 @@ describe("escrow", () => {
   });
 
   it("Exchange escrow", async () => {
-    await program.rpc.exchange({
+    await program.rpc.exchange(new anchor.BN(takerAmount), {
       accounts: {
         taker: takerMainAccount.publicKey,
         takerDepositTokenAccount: takerTokenAccountB,
@@ -176,6 +176,8 @@ describe("escrow", () => {
         vaultAccount: vault_account_pda,
         vaultAuthority: vault_authority_pda,
         tokenProgram: TOKEN_PROGRAM_ID,
+        initializerDepositMint: mintA.publicKey,
+        takerDepositMint: mintB.publicKey,
       },
       signers: [takerMainAccount]
     });
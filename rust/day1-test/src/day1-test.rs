@@ -1,5 +1,8 @@
 use std::fmt; // Import the `fmt` module.
+use std::str::FromStr;
+use std::ops::{Add, Sub};
 
+#[derive(Debug, PartialEq)]
 struct Color {
     red: u8,
     green: u8,
@@ -11,17 +14,125 @@ impl fmt::Display for Color {
         // RGB (128, 255, 90) 0x80FF5A
         // RGB (0, 3, 254) 0x0003FE
         // RGB (0, 0, 0) 0x000000
-        // TODO:
+        // each byte is always two hex digits
         fn hex(bytes: &[u8]) -> String {
-            bytes.iter().fold("".to_owned(), |s, b| format!("{}{:x}", s, b))
+            bytes.iter().fold("".to_owned(), |s, b| format!("{}{:02x}", s, b))
         }
         write!(f, "RGB ({}, {}, {}) 0x{}", self.red, self.green, self.blue, hex(&[self.red, self.green, self.blue]).to_uppercase())
     }
 }
 
-struct Matrix(f32, f32, f32, f32);
+impl Color {
+    // each channel moves from self towards other by alpha, clamped to [0.0, 1.0]
+    fn blend(&self, other: &Color, alpha: f32) -> Color {
+        let alpha = alpha.clamp(0.0, 1.0);
+        let mix = |a: u8, b: u8| (a as f32 * (1.0 - alpha) + b as f32 * alpha).round() as u8;
+        Color {
+            red: mix(self.red, other.red),
+            green: mix(self.green, other.green),
+            blue: mix(self.blue, other.blue),
+        }
+    }
+}
+
+impl From<(u8, u8, u8)> for Color {
+    fn from((red, green, blue): (u8, u8, u8)) -> Color {
+        Color { red, green, blue }
+    }
+}
+
+// packed as 0x00RRGGBB, the same digits the hex Display prints
+impl From<Color> for u32 {
+    fn from(color: Color) -> u32 {
+        (color.red as u32) << 16 | (color.green as u32) << 8 | color.blue as u32
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ColorParseError {
+    // not exactly six hex digits after the optional prefix
+    InvalidLength(usize),
+    InvalidCharacter(char),
+}
+
+impl fmt::Display for ColorParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ColorParseError::InvalidLength(len) => write!(f, "expected 6 hex digits, found {}", len),
+            ColorParseError::InvalidCharacter(c) => write!(f, "invalid hex digit {:?}", c),
+        }
+    }
+}
+
+// accepts "0003FE", "#0003FE" and "0x0003FE"
+impl FromStr for Color {
+    type Err = ColorParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.strip_prefix('#').or_else(|| s.strip_prefix("0x")).unwrap_or(s);
+        if let Some(c) = digits.chars().find(|c| !c.is_ascii_hexdigit()) {
+            return Err(ColorParseError::InvalidCharacter(c));
+        }
+        if digits.len() != 6 {
+            return Err(ColorParseError::InvalidLength(digits.len()));
+        }
+        let byte = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).unwrap();
+        Ok(Color { red: byte(0), green: byte(2), blue: byte(4) })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Matrix<const N: usize> {
+    data: [[f32; N]; N],
+}
+
+impl Matrix<2> {
+    fn new(a: f32, b: f32, c: f32, d: f32) -> Matrix<2> {
+        Matrix { data: [[a, b], [c, d]] }
+    }
+}
+
+impl<const N: usize> Matrix<N> {
+    // element (i, j) of the product is row i of self times column j of other
+    fn multiply(&self, other: &Matrix<N>) -> Matrix<N> {
+        Matrix {
+            data: std::array::from_fn(|i| {
+                std::array::from_fn(|j| (0..N).map(|k| self.data[i][k] * other.data[k][j]).sum())
+            }),
+        }
+    }
+
+    // Laplace expansion along the first row, for a 2x2 this is a*d - b*c
+    fn determinant(&self) -> f32 {
+        let rows: Vec<Vec<f32>> = self.data.iter().map(|row| row.to_vec()).collect();
+        laplace(&rows)
+    }
+
+    fn is_invertible(&self) -> bool {
+        self.determinant().abs() > DETERMINANT_EPSILON
+    }
+}
+
+const DETERMINANT_EPSILON: f32 = 1e-6;
 
-impl fmt::Display for Matrix {
+fn laplace(rows: &[Vec<f32>]) -> f32 {
+    match rows.len() {
+        0 => 1.0,
+        1 => rows[0][0],
+        n => (0..n)
+            .map(|j| {
+                let minor: Vec<Vec<f32>> = rows[1..]
+                    .iter()
+                    .map(|row| row.iter().enumerate().filter(|&(k, _)| k != j).map(|(_, &v)| v).collect())
+                    .collect();
+                let sign = if j % 2 == 0 { 1.0 } else { -1.0 };
+                sign * rows[0][j] * laplace(&minor)
+            })
+            .sum(),
+    }
+}
+
+impl<const N: usize> fmt::Display for Matrix<N> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         // Matrix:
         // ( 1.1 1.2 )
@@ -29,32 +140,116 @@ impl fmt::Display for Matrix {
         // Transpose:
         // ( 1.1 2.1 )
         // ( 1.2 2.2 )
-        // TODO:
-        write!(f, "( {} {} )\n( {} {} )",self.0, self.1, self.2, self.3)
+        for (i, row) in self.data.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "(")?;
+            for value in row {
+                write!(f, " {}", value)?;
+            }
+            write!(f, " )")?;
+        }
+        Ok(())
     }
 }
 
-fn transpose(matrix: Matrix) -> Matrix {
-    return Matrix(matrix.0, matrix.2, matrix.1, matrix.3)
+// element (i, j) of the transpose is element (j, i) of the original
+fn transpose<const N: usize>(matrix: Matrix<N>) -> Matrix<N> {
+    Matrix { data: std::array::from_fn(|i| std::array::from_fn(|j| matrix.data[j][i])) }
 }
 
+#[derive(Debug, PartialEq)]
 struct Point {
     x: i32,
     y: i32,
 }
 
+impl Point {
+    // differences are taken in f64, subtracting as i32 first could overflow
+    fn distance_to(&self, other: &Point) -> f64 {
+        let dx = self.x as f64 - other.x as f64;
+        let dy = self.y as f64 - other.y as f64;
+        dx.hypot(dy)
+    }
+
+    // summed in u64 so it can't overflow, distances past i32::MAX saturate
+    fn manhattan_distance(&self, other: &Point) -> i32 {
+        let distance = self.x.abs_diff(other.x) as u64 + self.y.abs_diff(other.y) as u64;
+        i32::try_from(distance).unwrap_or(i32::MAX)
+    }
+}
+
+impl Add for Point {
+    type Output = Point;
+
+    fn add(self, other: Point) -> Point {
+        Point { x: self.x + other.x, y: self.y + other.y }
+    }
+}
+
+impl Sub for Point {
+    type Output = Point;
+
+    fn sub(self, other: Point) -> Point {
+        Point { x: self.x - other.x, y: self.y - other.y }
+    }
+}
+
 struct Rectangle {
     top_left: Point,
     bottom_right: Point,
 }
 
 impl Rectangle {
+    // top_left always ends up with the smaller coordinates, whichever corners are given
+    fn from_corners(a: Point, b: Point) -> Rectangle {
+        Rectangle {
+            top_left: Point { x: a.x.min(b.x), y: a.y.min(b.y) },
+            bottom_right: Point { x: a.x.max(b.x), y: a.y.max(b.y) },
+        }
+    }
+
     fn area(&self) -> i32 {
         let top_edge:i32 = self.top_left.y;
         let left_edge:i32 = self.top_left.x;
         let bottom_edge:i32 = self.bottom_right.y;
         let right_edge:i32 = self.bottom_right.x;
-        (right_edge-left_edge) * (bottom_edge-top_edge)
+        (right_edge-left_edge).abs() * (bottom_edge-top_edge).abs()
+    }
+
+    // edges count as inside, works for either corner orientation
+    fn contains(&self, p: &Point) -> bool {
+        let (left, right) = (self.top_left.x.min(self.bottom_right.x), self.top_left.x.max(self.bottom_right.x));
+        let (top, bottom) = (self.top_left.y.min(self.bottom_right.y), self.top_left.y.max(self.bottom_right.y));
+        (left..=right).contains(&p.x) && (top..=bottom).contains(&p.y)
+    }
+
+    fn perimeter(&self) -> i32 {
+        let width = (self.bottom_right.x - self.top_left.x).abs();
+        let height = (self.bottom_right.y - self.top_left.y).abs();
+        2 * (width + height)
+    }
+
+    // scales width and height about the center, top_left always ends up with the smaller coordinates
+    fn scale(&self, factor: f64) -> Rectangle {
+        // corners are converted to f64 before adding, far apart corners would overflow an i32
+        let (left, top) = (self.top_left.x as f64, self.top_left.y as f64);
+        let (right, bottom) = (self.bottom_right.x as f64, self.bottom_right.y as f64);
+        let center_x = (left + right) / 2.0;
+        let center_y = (top + bottom) / 2.0;
+        let half_width = (right - left).abs() / 2.0 * factor.abs();
+        let half_height = (bottom - top).abs() / 2.0 * factor.abs();
+        Rectangle {
+            top_left: Point {
+                x: (center_x - half_width).round() as i32,
+                y: (center_y - half_height).round() as i32,
+            },
+            bottom_right: Point {
+                x: (center_x + half_width).round() as i32,
+                y: (center_y + half_height).round() as i32,
+            },
+        }
     }
 }
 
@@ -69,6 +264,12 @@ fn main() {
     ].iter() {
         println!("{:}", *color);
     }
+    // RGB (0, 3, 254) 0x0003FE
+    println!("{}", "#0003FE".parse::<Color>().unwrap());
+    // 0x80FF5A
+    println!("{:#08X}", u32::from(Color::from((128, 255, 90))));
+    // RGB (64, 129, 172) 0x4081AC
+    println!("{}", Color { red: 128, green: 255, blue: 90 }.blend(&Color { red: 0, green: 3, blue: 254 }, 0.5));
 
     // Matrix:
     // ( 1.1 1.2 )
@@ -76,9 +277,267 @@ fn main() {
     // Transpose:
     // ( 1.1 2.1 )
     // ( 1.2 2.2 )
-    let matrix = Matrix(1.1, 1.2, 2.1, 2.2);
+    let matrix = Matrix::new(1.1, 1.2, 2.1, 2.2);
     println!("Matrix:\n{}", matrix);
+    // Squared:
+    // ( 3.73 3.96 )
+    // ( 6.93 7.36 )
+    println!("Squared:\n{}", matrix.multiply(&matrix));
+    // Determinant: -0.10 (invertible: true)
+    println!("Determinant: {:.2} (invertible: {})", matrix.determinant(), matrix.is_invertible());
     println!("Transpose:\n{}", transpose(matrix));
     // Rect Area: 1
     println!("Rect Area: {}", Rectangle{top_left: Point{x:1, y:1}, bottom_right: Point{x:2, y:2}}.area());
+    // Rect Perimeter: 4
+    println!("Rect Perimeter: {}", Rectangle{top_left: Point{x:1, y:1}, bottom_right: Point{x:2, y:2}}.perimeter());
+    // Corners Rect Area: 3
+    println!("Corners Rect Area: {}", Rectangle::from_corners(Point{x:3, y:1} + Point{x:1, y:1}, Point{x:5, y:2} - Point{x:4, y:1}).area());
+    // Rect Contains (1, 2): true
+    println!("Rect Contains (1, 2): {}", Rectangle{top_left: Point{x:1, y:1}, bottom_right: Point{x:2, y:2}}.contains(&Point{x:1, y:2}));
+    // Scaled Rect Area: 4
+    println!("Scaled Rect Area: {}", Rectangle{top_left: Point{x:1, y:1}, bottom_right: Point{x:2, y:2}}.scale(2.0).area());
+    // Distance: 5
+    println!("Distance: {}", Point{x:0, y:0}.distance_to(&Point{x:3, y:4}));
+    // Manhattan Distance: 7
+    println!("Manhattan Distance: {}", Point{x:0, y:0}.manhattan_distance(&Point{x:3, y:4}));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_hex_is_zero_padded() {
+        assert_eq!(Color { red: 128, green: 255, blue: 90 }.to_string(), "RGB (128, 255, 90) 0x80FF5A");
+        assert_eq!(Color { red: 0, green: 3, blue: 254 }.to_string(), "RGB (0, 3, 254) 0x0003FE");
+        assert_eq!(Color { red: 0, green: 0, blue: 0 }.to_string(), "RGB (0, 0, 0) 0x000000");
+    }
+
+    #[test]
+    fn color_from_hex_str() {
+        let expected = Color { red: 0, green: 3, blue: 254 };
+        assert_eq!("0003FE".parse::<Color>(), Ok(expected));
+        assert_eq!(Color::from_str("#0003FE"), Ok(Color { red: 0, green: 3, blue: 254 }));
+        assert_eq!(Color::from_str("0x80ff5a"), Ok(Color { red: 128, green: 255, blue: 90 }));
+    }
+
+    #[test]
+    fn color_from_too_short_str() {
+        assert_eq!(Color::from_str("#03FE"), Err(ColorParseError::InvalidLength(4)));
+    }
+
+    #[test]
+    fn color_from_str_with_invalid_character() {
+        assert_eq!(Color::from_str("#00G3FE"), Err(ColorParseError::InvalidCharacter('G')));
+    }
+
+    #[test]
+    fn color_from_tuple_packs_into_u32() {
+        let color = Color::from((128, 255, 90));
+        assert_eq!(color, Color { red: 128, green: 255, blue: 90 });
+        assert_eq!(u32::from(color), 0x0080FF5A);
+    }
+
+    #[test]
+    fn zero_color_packs_to_zero() {
+        let packed: u32 = Color::from((0, 0, 0)).into();
+        assert_eq!(packed, 0);
+        assert_eq!(u32::from(Color { red: 255, green: 255, blue: 255 }), 0x00FFFFFF);
+    }
+
+    #[test]
+    fn blend_black_and_white_halfway() {
+        let black = Color { red: 0, green: 0, blue: 0 };
+        let white = Color { red: 255, green: 255, blue: 255 };
+        assert_eq!(black.blend(&white, 0.5), Color { red: 128, green: 128, blue: 128 });
+    }
+
+    #[test]
+    fn blend_at_the_ends_returns_either_color() {
+        let a = Color { red: 128, green: 255, blue: 90 };
+        let b = Color { red: 0, green: 3, blue: 254 };
+        assert_eq!(a.blend(&b, 0.0), Color { red: 128, green: 255, blue: 90 });
+        assert_eq!(a.blend(&b, 1.0), Color { red: 0, green: 3, blue: 254 });
+        // alpha outside [0.0, 1.0] is clamped
+        assert_eq!(a.blend(&b, -1.0), Color { red: 128, green: 255, blue: 90 });
+        assert_eq!(a.blend(&b, 2.0), Color { red: 0, green: 3, blue: 254 });
+    }
+
+    #[test]
+    fn transpose_2x2() {
+        let transposed = transpose(Matrix::new(1.1, 1.2, 2.1, 2.2));
+        assert_eq!(transposed, Matrix::new(1.1, 2.1, 1.2, 2.2));
+        assert_eq!(transposed.to_string(), "( 1.1 2.1 )\n( 1.2 2.2 )");
+    }
+
+    #[test]
+    fn transpose_3x3() {
+        let matrix = Matrix { data: [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]] };
+        let transposed = transpose(matrix);
+        assert_eq!(transposed.data, [[1.0, 4.0, 7.0], [2.0, 5.0, 8.0], [3.0, 6.0, 9.0]]);
+    }
+
+    fn assert_matrix_approx_eq<const N: usize>(actual: &Matrix<N>, expected: &Matrix<N>) {
+        for i in 0..N {
+            for j in 0..N {
+                assert!(
+                    (actual.data[i][j] - expected.data[i][j]).abs() < 1e-5,
+                    "{} != {} at ({}, {})",
+                    actual.data[i][j],
+                    expected.data[i][j],
+                    i,
+                    j
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn multiply_by_identity() {
+        let matrix = Matrix::new(1.1, 1.2, 2.1, 2.2);
+        let identity = Matrix::new(1.0, 0.0, 0.0, 1.0);
+        assert_matrix_approx_eq(&matrix.multiply(&identity), &matrix);
+        assert_matrix_approx_eq(&identity.multiply(&matrix), &matrix);
+    }
+
+    #[test]
+    fn multiply_hand_computed() {
+        let a = Matrix { data: [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]] };
+        let b = Matrix { data: [[9.0, 8.0, 7.0], [6.0, 5.0, 4.0], [3.0, 2.0, 1.0]] };
+        let expected = Matrix { data: [[30.0, 24.0, 18.0], [84.0, 69.0, 54.0], [138.0, 114.0, 90.0]] };
+        assert_matrix_approx_eq(&a.multiply(&b), &expected);
+
+        let m = Matrix::new(1.1, 1.2, 2.1, 2.2);
+        assert_matrix_approx_eq(&m.multiply(&m), &Matrix::new(3.73, 3.96, 6.93, 7.36));
+    }
+
+    #[test]
+    fn identity_is_invertible() {
+        let identity = Matrix::new(1.0, 0.0, 0.0, 1.0);
+        assert_eq!(identity.determinant(), 1.0);
+        assert!(identity.is_invertible());
+
+        let identity = Matrix { data: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]] };
+        assert_eq!(identity.determinant(), 1.0);
+        assert!(identity.is_invertible());
+    }
+
+    #[test]
+    fn repeated_row_is_not_invertible() {
+        let matrix = Matrix::new(1.1, 1.2, 1.1, 1.2);
+        assert_eq!(matrix.determinant(), 0.0);
+        assert!(!matrix.is_invertible());
+
+        let matrix = Matrix { data: [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [1.0, 2.0, 3.0]] };
+        assert_eq!(matrix.determinant(), 0.0);
+        assert!(!matrix.is_invertible());
+    }
+
+    #[test]
+    fn determinant_hand_computed() {
+        assert!((Matrix::new(1.1, 1.2, 2.1, 2.2).determinant() - -0.1).abs() < 1e-5);
+        let matrix = Matrix { data: [[2.0, 0.0, 1.0], [1.0, 3.0, 2.0], [1.0, 1.0, 2.0]] };
+        assert!((matrix.determinant() - 6.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn scale_by_one_keeps_area() {
+        let rect = Rectangle{top_left: Point{x:0, y:0}, bottom_right: Point{x:2, y:4}};
+        assert_eq!(rect.scale(1.0).area(), rect.area());
+    }
+
+    #[test]
+    fn scale_by_two_quadruples_area() {
+        let rect = Rectangle{top_left: Point{x:0, y:0}, bottom_right: Point{x:2, y:4}};
+        assert_eq!(rect.scale(2.0).area(), rect.area() * 4);
+    }
+
+    #[test]
+    fn scale_far_apart_corners() {
+        let rect = Rectangle{top_left: Point{x:i32::MAX - 4, y:i32::MIN}, bottom_right: Point{x:i32::MAX, y:i32::MIN + 2}};
+        let scaled = rect.scale(0.5);
+        assert_eq!(scaled.top_left, Point{x:i32::MAX - 3, y:i32::MIN});
+        assert_eq!(scaled.bottom_right, Point{x:i32::MAX - 1, y:i32::MIN + 1});
+    }
+
+    #[test]
+    fn area_and_perimeter() {
+        let rect = Rectangle{top_left: Point{x:1, y:2}, bottom_right: Point{x:4, y:6}};
+        assert_eq!(rect.area(), 12);
+        assert_eq!(rect.perimeter(), 14);
+    }
+
+    #[test]
+    fn swapped_corners_give_same_area_and_perimeter() {
+        let rect = Rectangle{top_left: Point{x:4, y:6}, bottom_right: Point{x:1, y:2}};
+        assert_eq!(rect.area(), 12);
+        assert_eq!(rect.perimeter(), 14);
+    }
+
+    #[test]
+    fn zero_width_rectangle() {
+        let rect = Rectangle{top_left: Point{x:3, y:0}, bottom_right: Point{x:3, y:5}};
+        assert_eq!(rect.area(), 0);
+        assert_eq!(rect.perimeter(), 10);
+    }
+
+    #[test]
+    fn point_add_and_sub() {
+        assert_eq!(Point{x:1, y:2} + Point{x:3, y:-4}, Point{x:4, y:-2});
+        assert_eq!(Point{x:1, y:2} - Point{x:3, y:-4}, Point{x:-2, y:6});
+    }
+
+    #[test]
+    fn from_corners_normalizes_swapped_corners() {
+        let rect = Rectangle::from_corners(Point{x:4, y:1}, Point{x:1, y:6});
+        assert_eq!(rect.top_left, Point{x:1, y:1});
+        assert_eq!(rect.bottom_right, Point{x:4, y:6});
+        assert_eq!(rect.area(), 15);
+    }
+
+    #[test]
+    fn contains_point_inside() {
+        let rect = Rectangle{top_left: Point{x:0, y:0}, bottom_right: Point{x:4, y:4}};
+        assert!(rect.contains(&Point{x:2, y:3}));
+    }
+
+    #[test]
+    fn contains_point_on_edge() {
+        let rect = Rectangle{top_left: Point{x:4, y:4}, bottom_right: Point{x:0, y:0}};
+        assert!(rect.contains(&Point{x:0, y:2}));
+        assert!(rect.contains(&Point{x:4, y:4}));
+    }
+
+    #[test]
+    fn does_not_contain_point_outside() {
+        let rect = Rectangle{top_left: Point{x:0, y:0}, bottom_right: Point{x:4, y:4}};
+        assert!(!rect.contains(&Point{x:5, y:2}));
+        assert!(!rect.contains(&Point{x:2, y:-1}));
+    }
+
+    #[test]
+    fn distance_of_3_4_5_triangle() {
+        assert_eq!(Point { x: 0, y: 0 }.distance_to(&Point { x: 3, y: 4 }), 5.0);
+        assert_eq!(Point { x: 3, y: 4 }.distance_to(&Point { x: 0, y: 0 }), 5.0);
+    }
+
+    #[test]
+    fn distance_does_not_overflow() {
+        let a = Point { x: i32::MIN, y: 0 };
+        let b = Point { x: i32::MAX, y: 0 };
+        assert_eq!(a.distance_to(&b), u32::MAX as f64);
+    }
+
+    #[test]
+    fn manhattan_distance_of_3_4() {
+        assert_eq!(Point { x: 0, y: 0 }.manhattan_distance(&Point { x: 3, y: 4 }), 7);
+        assert_eq!(Point { x: 3, y: -4 }.manhattan_distance(&Point { x: 0, y: 0 }), 7);
+    }
+
+    #[test]
+    fn manhattan_distance_saturates_instead_of_overflowing() {
+        let a = Point { x: i32::MIN, y: i32::MIN };
+        let b = Point { x: i32::MAX, y: i32::MAX };
+        assert_eq!(a.manhattan_distance(&b), i32::MAX);
+    }
 }
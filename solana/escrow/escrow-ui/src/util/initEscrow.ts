@@ -1,7 +1,7 @@
 import { AccountLayout, Token, TOKEN_PROGRAM_ID } from "@solana/spl-token";
 import { Account, Connection, PublicKey, SystemProgram, SYSVAR_RENT_PUBKEY, Transaction, TransactionInstruction } from "@solana/web3.js";
 import BN from "bn.js";
-import { ESCROW_ACCOUNT_DATA_LAYOUT, EscrowLayout } from "./layout";
+import { ESCROW_ACCOUNT_DATA_LAYOUT, EscrowLayout, MAX_SEED_LEN } from "./layout";
 
 const connection = new Connection("http://localhost:8899", 'singleGossip');
 
@@ -11,7 +11,9 @@ export const initEscrow = async (
     amountXTokensToSendToEscrow: number,
     initializerReceivingTokenAccountPubkeyString: string,
     expectedAmount: number,
-    escrowProgramIdString: string) => {
+    escrowProgramIdString: string,
+    // the trade can no longer be taken after this unix timestamp, a day from now by default
+    unlockTimestamp: number = Math.floor(Date.now() / 1000) + 24 * 60 * 60) => {
     const initializerXTokenAccountPubkey = new PublicKey(initializerXTokenAccountPubkeyString);
 
     //@ts-expect-error
@@ -43,6 +45,10 @@ export const initEscrow = async (
         programId: escrowProgramId
     });
 
+    // the PDA holding the temp token account is derived from [b"escrow", initializer, seed],
+    // the escrow account's own key is unique per trade so its first bytes make a fine seed
+    const seed = escrowAccount.publicKey.toBuffer().slice(0, MAX_SEED_LEN);
+
     const initEscrowIx = new TransactionInstruction({
         programId: escrowProgramId,
         keys: [
@@ -53,7 +59,13 @@ export const initEscrow = async (
             { pubkey: SYSVAR_RENT_PUBKEY, isSigner: false, isWritable: false},
             { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
         ],
-        data: Buffer.from(Uint8Array.of(0, ...new BN(expectedAmount).toArray("le", 8)))
+        data: Buffer.from(Uint8Array.of(
+            0,
+            ...new BN(expectedAmount).toArray("le", 8),
+            ...new BN(unlockTimestamp).toTwos(64).toArray("le", 8),
+            seed.length,
+            ...seed,
+        ))
     })
 
     const tx = new Transaction()
@@ -70,6 +82,7 @@ export const initEscrow = async (
         initializerAccountPubkey: new PublicKey(decodedEscrowState.initializerPubkey).toBase58(),
         XTokenTempAccountPubkey: new PublicKey(decodedEscrowState.initializerTempTokenAccountPubkey).toBase58(),
         initializerYTokenAccount: new PublicKey(decodedEscrowState.initializerReceivingTokenAccountPubkey).toBase58(),
-        expectedAmount: new BN(decodedEscrowState.expectedAmount, 10, "le").toNumber()
+        expectedAmount: new BN(decodedEscrowState.expectedAmount, 10, "le").toNumber(),
+        unlockTimestamp: new BN(decodedEscrowState.unlockTimestamp, 10, "le").fromTwos(64).toNumber()
     };
 }
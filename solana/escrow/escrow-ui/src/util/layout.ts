@@ -14,12 +14,30 @@ const uint64 = (property = "uint64") => {
   return BufferLayout.blob(8, property);
 };
 
+/**
+ * Layout for a 64bit signed value
+ */
+const int64 = (property = "int64") => {
+  return BufferLayout.blob(8, property);
+};
+
+// MEMO_LEN and MAX_SEED_LEN in the program's state.rs
+export const MEMO_LEN = 64;
+export const MAX_SEED_LEN = 16;
+
 export const ESCROW_ACCOUNT_DATA_LAYOUT = BufferLayout.struct([
   BufferLayout.u8("isInitialized"),
   publicKey("initializerPubkey"),
   publicKey("initializerTempTokenAccountPubkey"),
   publicKey("initializerReceivingTokenAccountPubkey"),
   uint64("expectedAmount"),
+  BufferLayout.blob(MEMO_LEN, "memo"),
+  publicKey("expectedMint"),
+  int64("unlockTimestamp"),
+  uint64("remainingAmount"),
+  BufferLayout.u8("seedLen"),
+  BufferLayout.blob(MAX_SEED_LEN, "seed"),
+  BufferLayout.u8("bump"),
 ]);
 
 export interface EscrowLayout {
@@ -27,5 +45,12 @@ export interface EscrowLayout {
   initializerPubkey: Uint8Array,
   initializerReceivingTokenAccountPubkey: Uint8Array,
   initializerTempTokenAccountPubkey: Uint8Array,
-  expectedAmount: Uint8Array
+  expectedAmount: Uint8Array,
+  memo: Uint8Array,
+  expectedMint: Uint8Array,
+  unlockTimestamp: Uint8Array,
+  remainingAmount: Uint8Array,
+  seedLen: number,
+  seed: Uint8Array,
+  bump: number
 }
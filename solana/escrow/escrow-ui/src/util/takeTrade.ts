@@ -1,5 +1,5 @@
 import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
-import { Account, Connection, PublicKey, Transaction, TransactionInstruction } from "@solana/web3.js";
+import { Account, Connection, PublicKey, SYSVAR_CLOCK_PUBKEY, Transaction, TransactionInstruction } from "@solana/web3.js";
 import BN from "bn.js";
 import { ESCROW_ACCOUNT_DATA_LAYOUT, EscrowLayout } from "./layout"; 
 
@@ -32,10 +32,18 @@ export const takeTrade = async (
         initializerAccountPubkey: new PublicKey(decodedEscrowLayout.initializerPubkey),
         XTokenTempAccountPubkey: new PublicKey(decodedEscrowLayout.initializerTempTokenAccountPubkey),
         initializerYTokenAccount: new PublicKey(decodedEscrowLayout.initializerReceivingTokenAccountPubkey),
-        expectedAmount: new BN(decodedEscrowLayout.expectedAmount, 10, "le")
+        expectedAmount: new BN(decodedEscrowLayout.expectedAmount, 10, "le"),
+        expectedMint: new PublicKey(decodedEscrowLayout.expectedMint),
+        seed: Buffer.from(decodedEscrowLayout.seed.slice(0, decodedEscrowLayout.seedLen))
     };
 
-    const PDA = await PublicKey.findProgramAddress([Buffer.from("escrow")], programId);
+    //@ts-expect-error
+    const XTokenMintAccountPubkey = new PublicKey((await connection.getParsedAccountInfo(escrowState.XTokenTempAccountPubkey, 'singleGossip')).value!.data.parsed.info.mint);
+
+    const PDA = await PublicKey.findProgramAddress(
+        [Buffer.from("escrow"), escrowState.initializerAccountPubkey.toBuffer(), escrowState.seed],
+        programId,
+    );
 
     const exchangeInstruction = new TransactionInstruction({
         programId,
@@ -49,7 +57,10 @@ export const takeTrade = async (
             { pubkey: escrowState.initializerYTokenAccount, isSigner: false, isWritable: true},
             { pubkey: escrowAccountPubkey, isSigner: false, isWritable: true },
             { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false},
-            { pubkey: PDA[0], isSigner: false, isWritable: false}
+            { pubkey: PDA[0], isSigner: false, isWritable: false},
+            { pubkey: SYSVAR_CLOCK_PUBKEY, isSigner: false, isWritable: false},
+            { pubkey: escrowState.expectedMint, isSigner: false, isWritable: false},
+            { pubkey: XTokenMintAccountPubkey, isSigner: false, isWritable: false}
         ] 
     })    
 
@@ -0,0 +1,11 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use solana_escrow::{instruction::EscrowInstruction, processor::Processor};
+use solana_program::pubkey::Pubkey;
+
+// arbitrary instruction data must be rejected with an Err, never a panic
+fuzz_target!(|data: &[u8]| {
+    let _ = EscrowInstruction::unpack(data);
+    // with no accounts the processor must still return, not panic
+    let _ = Processor::run_instruction(&Pubkey::default(), &[], data);
+});
@@ -1,9 +1,11 @@
 // program specific errors
 use thiserror::Error;
 
-use solana_program::program_error::ProgramError;
+use std::convert::TryFrom;
 
-#[derive(Error, Debug, Copy, Clone)]
+use solana_program::{decode_error::DecodeError, program_error::ProgramError};
+
+#[derive(Error, Debug, Copy, Clone, PartialEq)]
 pub enum EscrowError {
     /// Invalid instruction
     #[error("Invalid Instruction")]
@@ -15,10 +17,76 @@ pub enum EscrowError {
     ExpectedAmountMismatch,
     #[error("Amount Overflow")]
     AmountOverflow,
+    /// Escrow amounts must be nonzero
+    #[error("Invalid Amount")]
+    InvalidAmount,
+    /// The PDA's temp token account holds nothing to exchange
+    #[error("Amount Too Small")]
+    AmountTooSmall,
+    /// The escrow's unlock timestamp has passed
+    #[error("Escrow Expired")]
+    EscrowExpired,
+    /// Too many tokens are left in the escrow to reclaim them as dust
+    #[error("Not Dust")]
+    NotDust,
+    /// The PDA seed must be 1 to MAX_SEED_LEN bytes
+    #[error("Invalid Seed")]
+    InvalidSeed,
 }
 
 impl From<EscrowError> for ProgramError {
     fn from(e: EscrowError) -> Self {
         ProgramError::Custom(e as u32)
     }
-}
\ No newline at end of file
+}
+
+impl<T> DecodeError<T> for EscrowError {
+    fn type_of() -> &'static str {
+        "EscrowError"
+    }
+}
+
+/// Maps the code of a `ProgramError::Custom` back to its variant, for clients decoding errors
+impl TryFrom<u32> for EscrowError {
+    type Error = ();
+
+    fn try_from(code: u32) -> Result<Self, Self::Error> {
+        match code {
+            0 => Ok(EscrowError::InvalidInstruction),
+            1 => Ok(EscrowError::NotRentExempt),
+            2 => Ok(EscrowError::ExpectedAmountMismatch),
+            3 => Ok(EscrowError::AmountOverflow),
+            4 => Ok(EscrowError::InvalidAmount),
+            5 => Ok(EscrowError::AmountTooSmall),
+            6 => Ok(EscrowError::EscrowExpired),
+            7 => Ok(EscrowError::NotDust),
+            8 => Ok(EscrowError::InvalidSeed),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escrow_error_round_trips_through_code() {
+        let variants = [
+            EscrowError::InvalidInstruction,
+            EscrowError::NotRentExempt,
+            EscrowError::ExpectedAmountMismatch,
+            EscrowError::AmountOverflow,
+            EscrowError::InvalidAmount,
+            EscrowError::AmountTooSmall,
+            EscrowError::EscrowExpired,
+            EscrowError::NotDust,
+            EscrowError::InvalidSeed,
+        ];
+        for variant in variants.iter() {
+            assert_eq!(EscrowError::try_from(*variant as u32), Ok(*variant));
+        }
+        assert_eq!(EscrowError::try_from(variants.len() as u32), Err(()));
+        assert_eq!(<EscrowError as DecodeError<EscrowError>>::type_of(), "EscrowError");
+    }
+}
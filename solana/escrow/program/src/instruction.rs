@@ -1,9 +1,11 @@
 // program API, de(serializing) instruction data
 use std::convert::TryInto;
-use solana_program::program_error::ProgramError;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
 
-use crate::error::EscrowError::InvalidInstruction;
+use crate::error::EscrowError::{InvalidInstruction, InvalidSeed};
+use crate::state::{MAX_SEED_LEN, MEMO_LEN};
 
+#[derive(Debug, PartialEq)]
 pub enum EscrowInstruction {
     /// Starts the trade by creating and populating an escrow account and transfering ownership of the given temp token account to the PDA
     ///
@@ -20,9 +22,18 @@ pub enum EscrowInstruction {
     InitEscrow {
         // The amount party A expects to receive of token Y
         amount: u64,
+        /// unix timestamp after which the trade can no longer be taken
+        unlock_timestamp: i64,
+        /// 1 to MAX_SEED_LEN bytes following the timestamp, prefixed with their length,
+        /// the temp token account is handed to the PDA derived from `[b"escrow", initializer, seed]`
+        seed: Vec<u8>,
+        /// optional note from the maker, up to MEMO_LEN bytes following the seed and zero padded
+        memo: [u8; MEMO_LEN],
+        /// optional byte following the full MEMO_LEN memo, 1 requires the receive account to be owned by the initializer
+        require_self_receive: bool,
     },
 
-    /// Accepts a trade
+    /// Accepts a trade, either fully or partially. The escrow is closed once nothing is left in it
     ///
     ///
     /// Accounts expected:
@@ -35,10 +46,76 @@ pub enum EscrowInstruction {
     /// 6. `[writable]` The escrow account holding the escrow info
     /// 7. `[]` The token program
     /// 8. `[]` The PDA account
+    /// 9. `[]` The clock sysvar
+    /// 10. `[]` The mint of the token the taker sends, the escrow's expected mint
+    /// 11. `[]` The mint of the escrowed token the taker receives
+    ///
+    /// Both transfers go through `transfer_checked`, with the decimals read from these mints
     Exchange {
         /// the amount the taker expects to be paid in the other token, as a u64 because that's the max possible supply of a token
+        /// it may be less than what is left in the escrow, the taker then pays a proportional share of the expected amount
         amount: u64,
-    }
+    },
+
+    /// Cancels a trade nobody has taken, returning the tokens to the initializer
+    ///
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]`   The account of the person who initialized the escrow
+    /// 1. `[writable]` The PDA's temp token account to get tokens from and eventually close
+    /// 2. `[writable]` The initializer's token account that will get the tokens back
+    /// 3. `[writable]` The escrow account holding the escrow info
+    /// 4. `[]` The token program
+    /// 5. `[]` The PDA account
+    CancelEscrow,
+
+    /// Returns the program `VERSION` as little endian return data, no accounts expected
+    Version,
+
+    /// Hands an open escrow over to a new initializer, who gets paid when it is taken.
+    /// The temp token account moves to the new initializer's PDA along with it
+    ///
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]`   The account of the current initializer
+    /// 1. `[writable]` The escrow account holding the escrow info
+    /// 2. `[]`         The new initializer's token account that will receive tokens
+    /// 3. `[writable]` The PDA's temp token account
+    /// 4. `[]`         The token program
+    /// 5. `[]`         The current PDA account
+    TransferOwnership {
+        new_initializer: Pubkey,
+    },
+
+    /// Sweeps the dust left by partial fills back to the initializer and closes the escrow,
+    /// only once fewer than `MIN_FILL` tokens remain
+    ///
+    ///
+    /// Accounts expected: the same as `CancelEscrow`
+    ReclaimDust,
+
+    /// Tops up an open escrow before it is taken, raising what the initializer expects in return
+    ///
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]`   The account of the person who initialized the escrow
+    /// 1. `[writable]` The initializer's token account to send the extra tokens from
+    /// 2. `[writable]` The PDA's temp token account receiving the extra tokens
+    /// 3. `[writable]` The escrow account holding the escrow info
+    /// 4. `[]` The token program
+    Deposit {
+        /// the number of tokens added to the escrow
+        amount: u64,
+        /// added to the amount the initializer expects, following `amount`
+        expected_amount_delta: u64,
+    },
+
+    /// Logs the escrow's fields without changing anything, meant to be simulated by clients
+    ///
+    ///
+    /// Accounts expected:
+    /// 0. `[]` The escrow account holding the escrow info
+    DescribeEscrow,
 }
 
 /// Structと同じようにEnumへもメソッドを実装できる。
@@ -49,26 +126,220 @@ impl EscrowInstruction {
         let (tag, rest) = input.split_first().ok_or(InvalidInstruction)?;
         // tagの値に応じてOK<Self::hoge>かErr<fuga>を返す
         Ok(match tag {
-            0 => Self::InitEscrow {
-                amount: Self::unpack_amount(rest)?,
+            0 => {
+                let amount = Self::unpack_amount(rest)?;
+                let unlock_timestamp = Self::unpack_timestamp(&rest[8..])?;
+                let (seed, memo) = Self::unpack_seed(&rest[16..])?;
+                Self::InitEscrow {
+                    amount,
+                    unlock_timestamp,
+                    seed,
+                    memo: Self::unpack_memo(memo),
+                    require_self_receive: Self::unpack_flag(memo.get(MEMO_LEN..).unwrap_or(&[]))?,
+                }
             },
             1 => Self::Exchange {
                 amount: Self::unpack_amount(rest)?
             },
+            2 => Self::CancelEscrow,
+            3 => Self::Version,
+            4 => Self::TransferOwnership {
+                new_initializer: Self::unpack_pubkey(rest)?,
+            },
+            5 => Self::ReclaimDust,
+            6 => Self::Deposit {
+                amount: Self::unpack_amount(rest)?,
+                expected_amount_delta: Self::unpack_amount(&rest[8..])?,
+            },
+            7 => Self::DescribeEscrow,
             _ => return Err(InvalidInstruction.into()),
         })
     }
 
+    /// Packs a [EscrowInstruction](enum.EscrowInstruction.html) into the byte buffer `unpack` reads
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(1 + 8 + 8 + 1 + MAX_SEED_LEN + MEMO_LEN + 1);
+        match self {
+            Self::InitEscrow { amount, unlock_timestamp, seed, memo, require_self_receive } => {
+                buf.push(0);
+                buf.extend_from_slice(&amount.to_le_bytes());
+                buf.extend_from_slice(&unlock_timestamp.to_le_bytes());
+                buf.push(seed.len() as u8);
+                buf.extend_from_slice(seed);
+                buf.extend_from_slice(memo);
+                if *require_self_receive {
+                    buf.push(1);
+                }
+            }
+            Self::Exchange { amount } => {
+                buf.push(1);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::CancelEscrow => buf.push(2),
+            Self::Version => buf.push(3),
+            Self::TransferOwnership { new_initializer } => {
+                buf.push(4);
+                buf.extend_from_slice(new_initializer.as_ref());
+            }
+            Self::ReclaimDust => buf.push(5),
+            Self::Deposit { amount, expected_amount_delta } => {
+                buf.push(6);
+                buf.extend_from_slice(&amount.to_le_bytes());
+                buf.extend_from_slice(&expected_amount_delta.to_le_bytes());
+            }
+            Self::DescribeEscrow => buf.push(7),
+        }
+        buf
+    }
+
     fn unpack_amount(input: &[u8]) -> Result<u64, ProgramError> {
         let amount = input
-            /// byte配列を8個取り出す
+            // byte配列を8個取り出す
             .get(..8)
-            /// sliceであることを確認する
+            // sliceであることを確認する
             .and_then(|slice| slice.try_into().ok())
-            /// byte配列8個分をu64に変換する
+            // byte配列8個分をu64に変換する
             .map(u64::from_le_bytes)
             .ok_or(InvalidInstruction)?;
 
         Ok(amount)
     }
-}
\ No newline at end of file
+
+    fn unpack_timestamp(input: &[u8]) -> Result<i64, ProgramError> {
+        let timestamp = input
+            .get(..8)
+            .and_then(|slice| slice.try_into().ok())
+            .map(i64::from_le_bytes)
+            .ok_or(InvalidInstruction)?;
+
+        Ok(timestamp)
+    }
+
+    fn unpack_pubkey(input: &[u8]) -> Result<Pubkey, ProgramError> {
+        let pubkey = input
+            .get(..32)
+            .and_then(|slice| slice.try_into().ok())
+            .map(Pubkey::new_from_array)
+            .ok_or(InvalidInstruction)?;
+
+        Ok(pubkey)
+    }
+
+    /// 長さ1バイトに続くシードと、その後ろの残りのバイト列を返す
+    fn unpack_seed(input: &[u8]) -> Result<(Vec<u8>, &[u8]), ProgramError> {
+        let (len, rest) = input.split_first().ok_or(InvalidInstruction)?;
+        let len = *len as usize;
+        if len == 0 || len > MAX_SEED_LEN {
+            return Err(InvalidSeed.into());
+        }
+        let seed = rest.get(..len).ok_or(InvalidInstruction)?;
+
+        Ok((seed.to_vec(), &rest[len..]))
+    }
+
+    /// フラグは任意。無ければfalse、0か1以外の値や余分なバイトは拒否する
+    fn unpack_flag(input: &[u8]) -> Result<bool, ProgramError> {
+        match input {
+            [] | [0] => Ok(false),
+            [1] => Ok(true),
+            _ => Err(InvalidInstruction.into()),
+        }
+    }
+
+    /// memoは任意。足りないバイトは0で埋め、MEMO_LENを超えた分はフラグとして読む
+    fn unpack_memo(input: &[u8]) -> [u8; MEMO_LEN] {
+        let mut memo = [0u8; MEMO_LEN];
+        let len = input.len().min(MEMO_LEN);
+        memo[..len].copy_from_slice(&input[..len]);
+        memo
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::EscrowError;
+
+    #[test]
+    fn test_instruction_pack_round_trip() {
+        let mut memo = [0; MEMO_LEN];
+        memo[..5].copy_from_slice(b"hello");
+        let instructions = [
+            EscrowInstruction::InitEscrow {
+                amount: 1000,
+                unlock_timestamp: 1_700_000_000,
+                seed: b"offer-1".to_vec(),
+                memo,
+                require_self_receive: false,
+            },
+            EscrowInstruction::InitEscrow {
+                amount: 1000,
+                unlock_timestamp: 1_700_000_000,
+                seed: b"offer-1".to_vec(),
+                memo,
+                require_self_receive: true,
+            },
+            EscrowInstruction::Exchange { amount: 40 },
+            EscrowInstruction::CancelEscrow,
+            EscrowInstruction::Version,
+            EscrowInstruction::TransferOwnership { new_initializer: Pubkey::new_unique() },
+            EscrowInstruction::ReclaimDust,
+            EscrowInstruction::Deposit { amount: 50, expected_amount_delta: 500 },
+            EscrowInstruction::DescribeEscrow,
+        ];
+        for instruction in instructions {
+            assert_eq!(EscrowInstruction::unpack(&instruction.pack()).unwrap(), instruction);
+        }
+
+        // the tag is followed by the little endian amount
+        assert_eq!(EscrowInstruction::Exchange { amount: 40 }.pack(), [&[1][..], &40u64.to_le_bytes()].concat());
+
+        // the flag byte can only follow a full memo, and is either 0 or 1
+        let init = EscrowInstruction::InitEscrow {
+            amount: 1000,
+            unlock_timestamp: 1_700_000_000,
+            seed: b"offer-1".to_vec(),
+            memo,
+            require_self_receive: false,
+        }.pack();
+        assert_eq!(EscrowInstruction::unpack(&[&init[..], &[0]].concat()).unwrap(), EscrowInstruction::unpack(&init).unwrap());
+        assert_eq!(
+            EscrowInstruction::unpack(&[&init[..], &[2]].concat()).unwrap_err(),
+            ProgramError::from(EscrowError::InvalidInstruction),
+        );
+    }
+
+    #[test]
+    fn test_init_escrow_rejects_bad_seed_length() {
+        let mut data = vec![0];
+        data.extend_from_slice(&1000u64.to_le_bytes());
+        data.extend_from_slice(&i64::MAX.to_le_bytes());
+        data.push(0);
+        assert_eq!(EscrowInstruction::unpack(&data).err(), Some(ProgramError::from(EscrowError::InvalidSeed)));
+
+        data.pop();
+        data.push(17);
+        data.extend_from_slice(&[1; 17]);
+        assert_eq!(EscrowInstruction::unpack(&data).err(), Some(ProgramError::from(EscrowError::InvalidSeed)));
+    }
+
+    #[test]
+    fn test_unpack_rejects_malformed_data() {
+        let mut no_seed = vec![0];
+        no_seed.extend_from_slice(&[0; 16]);
+        let mut short_seed = no_seed.clone();
+        short_seed.extend_from_slice(&[5, 1, 2]);
+        let malformed: [&[u8]; 7] = [
+            &[],
+            &[6],
+            &[0, 1, 2, 3],
+            &[0; 9],
+            &no_seed,
+            &short_seed,
+            &[4; 20],
+        ];
+        for data in malformed.iter() {
+            assert!(EscrowInstruction::unpack(data).is_err(), "{:?} unpacked", data);
+        }
+    }
+}
@@ -5,5 +5,8 @@ pub mod instruction;
 pub mod processor;
 pub mod state;
 
+/// Version of this program, returned by the `Version` instruction
+pub const VERSION: u32 = 1;
+
 #[cfg(not(feature = "no-entrypoint"))]
 pub mod entrypoint;
\ No newline at end of file
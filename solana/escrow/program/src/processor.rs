@@ -1,4 +1,10 @@
-// program logic
+//! program logic
+//!
+//! Off-chain indexers can rely on two structured log lines, whose format is kept stable:
+//!
+//! - `ESCROW_INIT initializer=<pubkey> expected=<u64> temp=<pubkey>` at the end of `InitEscrow`
+//! - `ESCROW_FILLED taker=<pubkey> amount=<u64>` at the end of every (partial) `Exchange`,
+//!   where `amount` is the number of tokens the taker received from the escrow
 
 use solana_program::{
     account_info::{ next_account_info, AccountInfo },
@@ -6,14 +12,31 @@ use solana_program::{
     program_error::ProgramError,
     msg,
     pubkey::Pubkey,
-    program::{invoke},
-    program_pack::{Pack, IsInitialized },
-    sysvar::{ rent::Rent, Sysvar },
+    program::{invoke, set_return_data},
+    program_pack::Pack,
+    sysvar::{ clock::Clock, rent::Rent, Sysvar },
 };
 use spl_token::solana_program::program::invoke_signed;
-use spl_token::state::Account as TokenAccount;
+use spl_token::state::{Account as TokenAccount, Mint};
 
-use crate::{instruction::EscrowInstruction, error::EscrowError, state::Escrow};
+use crate::{instruction::EscrowInstruction, error::EscrowError, state::{find_escrow_pda, Escrow, MAX_SEED_LEN, MEMO_LEN, MIN_FILL}, VERSION};
+
+/// `next_account_info`, logging which account the caller left out
+fn expect_account<'a, 'b, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    iter: &mut I,
+    name: &str,
+) -> Result<I::Item, ProgramError> {
+    next_account_info(iter).inspect_err(|_| msg!("Missing account: {}", name))
+}
+
+/// rejects escrow accounts owned by another program, before their data is read or overwritten
+fn check_escrow_owner(escrow_account: &AccountInfo, program_id: &Pubkey) -> ProgramResult {
+    if escrow_account.owner != program_id {
+        msg!("Escrow account {} is not owned by this program", escrow_account.key);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
 
 pub struct Processor;
 impl Processor {
@@ -26,65 +49,141 @@ impl Processor {
         let instruction = EscrowInstruction::unpack(instruction_data)?;
 
         match instruction {
-            EscrowInstruction::InitEscrow { amount } => {
+            EscrowInstruction::InitEscrow { amount, unlock_timestamp, seed, memo, require_self_receive } => {
                 msg!("Instruction: InitEscrow");
-                Self::process_init_escrow(accounts, amount, program_id)
+                Self::process_init_escrow(accounts, amount, unlock_timestamp, &seed, memo, require_self_receive, program_id)
             },
             EscrowInstruction::Exchange { amount } => {
                 msg!("Instruction: Exchange");
                 Self::process_exchange(accounts, amount, program_id)
+            },
+            EscrowInstruction::CancelEscrow => {
+                msg!("Instruction: CancelEscrow");
+                Self::process_cancel(accounts, program_id, false)
+            },
+            EscrowInstruction::Version => {
+                msg!("Instruction: Version");
+                set_return_data(&VERSION.to_le_bytes());
+                Ok(())
+            },
+            EscrowInstruction::TransferOwnership { new_initializer } => {
+                msg!("Instruction: TransferOwnership");
+                Self::process_transfer_ownership(accounts, new_initializer, program_id)
+            },
+            EscrowInstruction::ReclaimDust => {
+                msg!("Instruction: ReclaimDust");
+                // 残りはキャンセルと同じく、署名とアカウントを検証して返金し、アカウントを閉じる
+                Self::process_cancel(accounts, program_id, true)
+            },
+            EscrowInstruction::Deposit { amount, expected_amount_delta } => {
+                msg!("Instruction: Deposit");
+                Self::process_deposit(accounts, amount, expected_amount_delta, program_id)
+            },
+            EscrowInstruction::DescribeEscrow => {
+                msg!("Instruction: DescribeEscrow");
+                Self::process_describe(accounts, program_id)
             }
         }
     }
 
+    /// Same as `process`, for fuzz harnesses driving the processor without the `entrypoint!` macro
+    #[cfg(fuzzing)]
+    pub fn run_instruction(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        instruction_data: &[u8]
+    ) -> ProgramResult {
+        Self::process(program_id, accounts, instruction_data)
+    }
+
     fn process_init_escrow(
         accounts: &[AccountInfo],
         amount: u64,
+        unlock_timestamp: i64,
+        seed: &[u8],
+        memo: [u8; MEMO_LEN],
+        require_self_receive: bool,
         program_id: &Pubkey,
     ) -> ProgramResult {
+        // 0トークンを要求するEscrowは何も送らずに約定できてしまうので拒否する
+        if amount == 0 {
+            return Err(EscrowError::InvalidAmount.into());
+        }
+
         let account_info_iter = &mut accounts.iter();
-        /// 0. `[signer]`   The account of the person initializing the escrow
-        let initializer = next_account_info(account_info_iter)?;
+        // 0. `[signer]`   The account of the person initializing the escrow
+        let initializer = expect_account(account_info_iter, "initializer")?;
         
         if !initializer.is_signer {
             return Err(ProgramError::MissingRequiredSignature);
         }
-        /// 1. `[writable]` Temporary token account that should be created prior to this instruction and owned by the initializer
-        let temp_token_account = next_account_info(account_info_iter)?;
+        // 1. `[writable]` Temporary token account that should be created prior to this instruction and owned by the initializer
+        let temp_token_account = expect_account(account_info_iter, "temp_token_account")?;
+        let temp_token_account_info =
+            TokenAccount::unpack(&temp_token_account.try_borrow_data()?)?;
 
-        /// 2. `[]`         The initializer's token account for the token they will receive should the trade go through
-        let token_to_receive_account = next_account_info(account_info_iter)?;
+        // 2. `[]`         The initializer's token account for the token they will receive should the trade go through
+        let token_to_receive_account = expect_account(account_info_iter, "token_to_receive_account")?;
         if *token_to_receive_account.owner != spl_token::id() {
             return Err(ProgramError::IncorrectProgramId);
         }
+        let token_to_receive_account_info =
+            TokenAccount::unpack(&token_to_receive_account.try_borrow_data()?)?;
+        // フラグが立っていれば、受け取ったトークンがイニシャライザー以外に流れないようにする
+        if require_self_receive && token_to_receive_account_info.owner != *initializer.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
 
-        /// 3. `[writable]` The escrow account, it will hold all necessary info about the trade
-        let escrow_account = next_account_info(account_info_iter)?;
-        /// 4. `[]`         The rent sysvar
+        // 3. `[writable]` The escrow account, it will hold all necessary info about the trade
+        let escrow_account = expect_account(account_info_iter, "escrow_account")?;
+        // 4. `[]`         The rent sysvar
         let rent = &Rent::from_account_info(
-            next_account_info(account_info_iter)?
+            expect_account(account_info_iter, "rent_sysvar")?
         )?;
         // 新規作成したEscrow情報を保持するアカウントが、家賃免除とされるlamports以上を保有していなければ、リバートする。
         if !rent.is_exempt(escrow_account.lamports(), escrow_account.data_len()) {
             return Err(EscrowError::NotRentExempt.into());
         }
-        // Escrow情報を保持するアカウントアドレスをEscrow型にキャストする。
-        let mut escrow_info = Escrow::unpack_unchecked(&escrow_account.data.borrow())?;
-        // Escrowアカウントが初期化済みであればリバートする。
-        if escrow_info.is_initialized() {
+        // Escrowアカウントが初期化済みであればリバートする。（初期化フラグの1バイトだけを読む）
+        if Escrow::is_account_initialized(&escrow_account.data.borrow())? {
             return Err(ProgramError::AccountAlreadyInitialized);
         }
+        // PDAアカウント＝プログラム派生アカウント
+        // イニシャライザーの公開鍵と指定したシードからPDAを生成する。Escrowごとに別のPDAを持てる。
+        // 別のイニシャライザーが同じシードを選んでもPDAは衝突しない。
+        // バンプはEscrowに保存し、以降の命令では探し直さない
+        let (pda, bump_seed) = find_escrow_pda(program_id, initializer.key, seed);
+
         // Escrowアカウントの各属性に値を書き込む
-        // 初期化フラグを立てる
-        escrow_info.is_initialized = true;
-        // Escrowアカウントを初期化した張本人の公開鍵を格納する。
-        escrow_info.initializer_pubkey = *initializer.key;
-        // Escrowアカウントがテイカーに向けてトークンを送付する際に使用するアカウントの公開鍵を格納する。
-        escrow_info.temp_token_account_pubkey = *temp_token_account.key;
-        // Escrowアカウントからトークンを受け取る一時アカウントの公開鍵を格納する。
-        escrow_info.initializer_token_to_receive_account_pubkey = *token_to_receive_account.key;
-        // 初期化した張本人が要求するトークン数量を格納する。
-        escrow_info.expected_amount = amount;
+        let escrow_info = Escrow {
+            // 初期化フラグを立てる
+            is_initialized: true,
+            // Escrowアカウントを初期化した張本人の公開鍵を格納する。
+            initializer_pubkey: *initializer.key,
+            // Escrowアカウントがテイカーに向けてトークンを送付する際に使用するアカウントの公開鍵を格納する。
+            temp_token_account_pubkey: *temp_token_account.key,
+            // Escrowアカウントからトークンを受け取る一時アカウントの公開鍵を格納する。
+            initializer_token_to_receive_account_pubkey: *token_to_receive_account.key,
+            // 初期化した張本人が要求するトークン数量を格納する。
+            expected_amount: amount,
+            // メイカーのメモを格納する。
+            memo,
+            // テイカーから受け取るトークンのミントを格納する。
+            expected_mint: token_to_receive_account_info.mint,
+            // この時刻を過ぎるとテイカーは交換できなくなる。
+            unlock_timestamp,
+            // 部分約定で減っていく、Escrowに残っているトークン数量を格納する。
+            remaining_amount: temp_token_account_info.amount,
+            // PDAを導出するシードを格納する。
+            seed_len: seed.len() as u8,
+            seed: {
+                let mut padded = [0u8; MAX_SEED_LEN];
+                padded[..seed.len()].copy_from_slice(seed);
+                padded
+            },
+            // PDAのバンプシードを格納する。
+            bump: bump_seed,
+        };
 
         // 再格納する。（アカウントに情報を書き込む）
         Escrow::pack(
@@ -92,15 +191,12 @@ impl Processor {
             &mut escrow_account.try_borrow_mut_data()?
         )?;
 
-        // PDAアカウント＝プログラム派生アカウント
-        // 固定シード値を用いてPDAを生成する。
-        let (pda, _bump_seed) = Pubkey::find_program_address(
-            &[b"escrow"],
-            program_id
-        );
-
-        /// 5. `[]`         The token program
-        let token_program = next_account_info(account_info_iter)?;
+        // 5. `[]`         The token program
+        let token_program = expect_account(account_info_iter, "token_program")?;
+        // 本物のSPLトークンプログラム以外にCPIさせない
+        if *token_program.key != spl_token::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
 
         // Escrowアカウントがテイカーに向けてトークンを送付する際に使用するアカウントの所有者をPDAに変更する。
         let owner_change_ix = spl_token::instruction::set_authority(
@@ -122,6 +218,13 @@ impl Processor {
             ],
         )?;
 
+        msg!(
+            "ESCROW_INIT initializer={} expected={} temp={}",
+            initializer.key,
+            amount,
+            temp_token_account.key
+        );
+
         Ok(())
     }
 
@@ -132,7 +235,7 @@ impl Processor {
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         // テイカーのアカウント情報を格納する
-        let taker = next_account_info(account_info_iter)?;
+        let taker = expect_account(account_info_iter, "taker")?;
 
         // テイカーが署名者本人でなければリバートする
         if !taker.is_signer {
@@ -140,28 +243,56 @@ impl Processor {
         }
 
         // テイカーがトークンを送る際に使うアカウントを格納する
-        let takers_sending_token_account = next_account_info(account_info_iter)?;
+        let takers_sending_token_account = expect_account(account_info_iter, "takers_sending_token_account")?;
 
         // テイカーがトークンを受け取るアカウントを格納する
-        let takers_token_to_receive_account = next_account_info(account_info_iter)?;
+        let takers_token_to_receive_account = expect_account(account_info_iter, "takers_token_to_receive_account")?;
 
         // PDAに所有権を移譲されたアカウントを格納する
-        let pdas_temp_token_account = next_account_info(account_info_iter)?;
+        let pdas_temp_token_account = expect_account(account_info_iter, "pdas_temp_token_account")?;
         // TokenAccountにキャストする
         let pdas_temp_token_account_info =
             TokenAccount::unpack(&pdas_temp_token_account.try_borrow_data()?)?;
 
-        let (pda, bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
+        // 受け取り口座のミントが預けられたトークンと違えば、トークンプログラムの奥で失敗する前に拒否する
+        let takers_token_to_receive_account_info =
+            TokenAccount::unpack(&takers_token_to_receive_account.try_borrow_data()?)?;
+        if takers_token_to_receive_account_info.mint != pdas_temp_token_account_info.mint {
+            msg!(
+                "Taker's receive account mint {} does not match the escrowed mint {}",
+                takers_token_to_receive_account_info.mint,
+                pdas_temp_token_account_info.mint
+            );
+            return Err(ProgramError::InvalidAccountData);
+        }
 
-        if amount_expected_by_taker != pdas_temp_token_account_info.amount {
-            return Err(EscrowError::ExpectedAmountMismatch.into());
+        // 空の一時アカウントとの交換は何も受け取らずにEscrowを閉じてしまうので拒否する
+        if pdas_temp_token_account_info.amount == 0 {
+            return Err(EscrowError::AmountTooSmall.into());
         }
 
-        let initializers_main_account = next_account_info(account_info_iter)?;
-        let initializers_token_to_receive_account = next_account_info(account_info_iter)?;
-        let escrow_account = next_account_info(account_info_iter)?;
+        let initializers_main_account = expect_account(account_info_iter, "initializers_main_account")?;
+        let initializers_token_to_receive_account = expect_account(account_info_iter, "initializers_token_to_receive_account")?;
+        let escrow_account = expect_account(account_info_iter, "escrow_account")?;
 
-        let escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+        // 他のプログラムが所有するEscrowは読み取りもデータの消去もしない
+        check_escrow_owner(escrow_account, program_id)?;
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        // PDAはEscrowに格納されたシードとバンプから導出する
+        let pda = escrow_info.pda(program_id)?;
+        let bump_seed = escrow_info.bump;
+
+        // PDAが所有していない一時アカウントからは送金させない
+        if pdas_temp_token_account_info.owner != pda {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // Escrowに残っている以上の数量は受け取れない
+        if amount_expected_by_taker == 0 || amount_expected_by_taker > escrow_info.remaining_amount {
+            return Err(EscrowError::ExpectedAmountMismatch.into());
+        }
 
         if escrow_info.temp_token_account_pubkey != *pdas_temp_token_account.key {
             return Err(ProgramError::InvalidAccountData);
@@ -175,50 +306,125 @@ impl Processor {
             return Err(ProgramError::InvalidAccountData);
         }
 
-        let token_program = next_account_info(account_info_iter)?;
+        // テイカーが送るトークンのミントがイニシャライザーの要求するミントと一致しなければリバートする
+        let takers_sending_token_account_info =
+            TokenAccount::unpack(&takers_sending_token_account.try_borrow_data()?)?;
+        if takers_sending_token_account_info.mint != escrow_info.expected_mint {
+            return Err(ProgramError::InvalidAccountData);
+        }
 
-        let transfer_to_initializer_ix = spl_token::instruction::transfer(
+        msg!("Escrow memo: {}", String::from_utf8_lossy(escrow_info.memo_bytes()));
+
+        let token_program = expect_account(account_info_iter, "token_program")?;
+        // 本物のSPLトークンプログラム以外にCPIさせない
+        if *token_program.key != spl_token::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let pda_account = expect_account(account_info_iter, "pda_account")?;
+        let clock = &Clock::from_account_info(
+            expect_account(account_info_iter, "clock_sysvar")?
+        )?;
+
+        // 期限切れのEscrowは交換させない
+        if clock.unix_timestamp > escrow_info.unlock_timestamp {
+            return Err(EscrowError::EscrowExpired.into());
+        }
+
+        // transfer_checkedに渡すミントとその小数点桁数。ミントの取り違えはトークンプログラムに渡す前に拒否する
+        let sending_mint_account = expect_account(account_info_iter, "sending_mint_account")?;
+        if *sending_mint_account.key != escrow_info.expected_mint {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let sending_mint = Mint::unpack(&sending_mint_account.try_borrow_data()?)?;
+
+        let receiving_mint_account = expect_account(account_info_iter, "receiving_mint_account")?;
+        if *receiving_mint_account.key != pdas_temp_token_account_info.mint {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let receiving_mint = Mint::unpack(&receiving_mint_account.try_borrow_data()?)?;
+
+        // 受け取る数量に比例した分だけイニシャライザーに支払う
+        // amount_expected_by_taker <= remaining_amount なので結果はexpected_amount以下に収まる
+        let amount_to_initializer = (escrow_info.expected_amount as u128
+            * amount_expected_by_taker as u128
+            / escrow_info.remaining_amount as u128) as u64;
+        // 支払いが0になる端数は約定させない
+        if amount_to_initializer == 0 {
+            return Err(EscrowError::AmountTooSmall.into());
+        }
+
+        let transfer_to_initializer_ix = spl_token::instruction::transfer_checked(
             token_program.key,
             takers_sending_token_account.key,
+            sending_mint_account.key,
             initializers_token_to_receive_account.key,
             taker.key,
             &[&taker.key],
-            escrow_info.expected_amount,
+            amount_to_initializer,
+            sending_mint.decimals,
         )?;
         msg!("Calling the token program to transfer tokens to the escrow's initializer...");
         invoke(
             &transfer_to_initializer_ix,
             &[
                 takers_sending_token_account.clone(),
+                sending_mint_account.clone(),
                 initializers_token_to_receive_account.clone(),
                 taker.clone(),
                 token_program.clone(),
             ],
         )?;
-        
-        
-        let pda_account = next_account_info(account_info_iter)?;
 
-        let transfer_to_taker_ix = spl_token::instruction::transfer(
+        // 最後の約定では一時アカウントの実残高をすべて渡す
+        // 誰かが一時アカウントにトークンを送り付けていると、残高が残ってclose_accountが失敗するため
+        let amount_to_taker = if amount_expected_by_taker == escrow_info.remaining_amount {
+            pdas_temp_token_account_info.amount.max(amount_expected_by_taker)
+        } else {
+            amount_expected_by_taker
+        };
+
+        let transfer_to_taker_ix = spl_token::instruction::transfer_checked(
             token_program.key,
             pdas_temp_token_account.key,
+            receiving_mint_account.key,
             takers_token_to_receive_account.key,
             &pda,
             &[&pda],
-            pdas_temp_token_account_info.amount,
+            amount_to_taker,
+            receiving_mint.decimals,
         )?;
         msg!("Calling the token program to transfer tokens to the taker...");
         invoke_signed(
             &transfer_to_taker_ix,
             &[
                 pdas_temp_token_account.clone(),
+                receiving_mint_account.clone(),
                 takers_token_to_receive_account.clone(),
                 pda_account.clone(),
                 token_program.clone(),
             ],
-            &[&[&b"escrow"[..], &[bump_seed]]],
+            &[&[&b"escrow"[..], escrow_info.initializer_pubkey.as_ref(), escrow_info.seed_bytes(), &[bump_seed]]],
         )?;
 
+        escrow_info.remaining_amount = escrow_info.remaining_amount
+            .checked_sub(amount_expected_by_taker)
+            .ok_or(EscrowError::AmountOverflow)?;
+        escrow_info.expected_amount = escrow_info.expected_amount
+            .checked_sub(amount_to_initializer)
+            .ok_or(EscrowError::AmountOverflow)?;
+
+        msg!("ESCROW_FILLED taker={} amount={}", taker.key, amount_expected_by_taker);
+
+        // まだトークンが残っていれば、残量を書き戻してEscrowを開いたままにする
+        if escrow_info.remaining_amount > 0 {
+            msg!("Escrow partially filled, {} left", escrow_info.remaining_amount);
+            Escrow::pack(
+                escrow_info,
+                &mut escrow_account.try_borrow_mut_data()?
+            )?;
+            return Ok(());
+        }
+
         let close_pdas_temp_acc_ix = spl_token::instruction::close_account(
             token_program.key,
             pdas_temp_token_account.key,
@@ -235,7 +441,7 @@ impl Processor {
                 pda_account.clone(),
                 token_program.clone(),
             ],
-            &[&[&b"escrow"[..], &[bump_seed]]],
+            &[&[&b"escrow"[..], escrow_info.initializer_pubkey.as_ref(), escrow_info.seed_bytes(), &[bump_seed]]],
         )?;
 
         msg!("Closing the escrow account...");
@@ -247,4 +453,263 @@ impl Processor {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// `dust_only` is set by ReclaimDust, which may only close escrows with less than MIN_FILL left
+    fn process_cancel(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        dust_only: bool,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let initializer = next_account_info(account_info_iter)?;
+
+        // キャンセルできるのはEscrowを初期化した本人のみ
+        if !initializer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let pdas_temp_token_account = next_account_info(account_info_iter)?;
+        let pdas_temp_token_account_info =
+            TokenAccount::unpack(&pdas_temp_token_account.try_borrow_data()?)?;
+        let initializers_token_account = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        check_escrow_owner(escrow_account, program_id)?;
+
+        let escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        // まだ約定できる量が残っているうちは回収させない
+        if dust_only && escrow_info.remaining_amount >= MIN_FILL {
+            return Err(EscrowError::NotDust.into());
+        }
+
+        if escrow_info.initializer_pubkey != *initializer.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        if escrow_info.temp_token_account_pubkey != *pdas_temp_token_account.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let token_program = next_account_info(account_info_iter)?;
+        // 本物のSPLトークンプログラム以外にPDAの署名を渡さない
+        if *token_program.key != spl_token::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let pda_account = next_account_info(account_info_iter)?;
+
+        let pda = escrow_info.pda(program_id)?;
+        let bump_seed = escrow_info.bump;
+
+        let transfer_to_initializer_ix = spl_token::instruction::transfer(
+            token_program.key,
+            pdas_temp_token_account.key,
+            initializers_token_account.key,
+            &pda,
+            &[&pda],
+            pdas_temp_token_account_info.amount,
+        )?;
+        msg!("Calling the token program to return tokens to the escrow's initializer...");
+        invoke_signed(
+            &transfer_to_initializer_ix,
+            &[
+                pdas_temp_token_account.clone(),
+                initializers_token_account.clone(),
+                pda_account.clone(),
+                token_program.clone(),
+            ],
+            &[&[&b"escrow"[..], escrow_info.initializer_pubkey.as_ref(), escrow_info.seed_bytes(), &[bump_seed]]],
+        )?;
+
+        let close_pdas_temp_acc_ix = spl_token::instruction::close_account(
+            token_program.key,
+            pdas_temp_token_account.key,
+            initializer.key,
+            &pda,
+            &[&pda]
+        )?;
+        msg!("Calling the token program to close pda's temp account...");
+        invoke_signed(
+            &close_pdas_temp_acc_ix,
+            &[
+                pdas_temp_token_account.clone(),
+                initializer.clone(),
+                pda_account.clone(),
+                token_program.clone(),
+            ],
+            &[&[&b"escrow"[..], escrow_info.initializer_pubkey.as_ref(), escrow_info.seed_bytes(), &[bump_seed]]],
+        )?;
+
+        msg!("Closing the escrow account...");
+        **initializer.lamports.borrow_mut() = initializer.lamports()
+            .checked_add(escrow_account.lamports())
+            .ok_or(EscrowError::AmountOverflow)?;
+        **escrow_account.lamports.borrow_mut() = 0;
+        *escrow_account.try_borrow_mut_data()? = &mut [];
+
+        Ok(())
+    }
+
+    fn process_deposit(
+        accounts: &[AccountInfo],
+        amount: u64,
+        expected_amount_delta: u64,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        if amount == 0 {
+            return Err(EscrowError::InvalidAmount.into());
+        }
+
+        let account_info_iter = &mut accounts.iter();
+        let initializer = next_account_info(account_info_iter)?;
+
+        // 追加で預けられるのはEscrowを初期化した本人のみ
+        if !initializer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let initializers_sending_token_account = next_account_info(account_info_iter)?;
+        let pdas_temp_token_account = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        // 約定済みのEscrowはアカウントごと閉じられ、プログラムの所有から外れているので、ここで失敗する
+        check_escrow_owner(escrow_account, program_id)?;
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        if escrow_info.initializer_pubkey != *initializer.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        if escrow_info.temp_token_account_pubkey != *pdas_temp_token_account.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let token_program = next_account_info(account_info_iter)?;
+        // 本物のSPLトークンプログラム以外にCPIさせない
+        if *token_program.key != spl_token::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        escrow_info.remaining_amount = escrow_info.remaining_amount
+            .checked_add(amount)
+            .ok_or(EscrowError::AmountOverflow)?;
+        escrow_info.expected_amount = escrow_info.expected_amount
+            .checked_add(expected_amount_delta)
+            .ok_or(EscrowError::AmountOverflow)?;
+
+        let transfer_to_pda_ix = spl_token::instruction::transfer(
+            token_program.key,
+            initializers_sending_token_account.key,
+            pdas_temp_token_account.key,
+            initializer.key,
+            &[initializer.key],
+            amount,
+        )?;
+        msg!("Calling the token program to deposit more tokens into the escrow...");
+        invoke(
+            &transfer_to_pda_ix,
+            &[
+                initializers_sending_token_account.clone(),
+                pdas_temp_token_account.clone(),
+                initializer.clone(),
+                token_program.clone(),
+            ],
+        )?;
+
+        msg!("Deposited {}, {} now held for {}", amount, escrow_info.remaining_amount, escrow_info.expected_amount);
+        Escrow::pack(
+            escrow_info,
+            &mut escrow_account.try_borrow_mut_data()?
+        )?;
+
+        Ok(())
+    }
+
+    fn process_transfer_ownership(
+        accounts: &[AccountInfo],
+        new_initializer: Pubkey,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let initializer = next_account_info(account_info_iter)?;
+
+        // 譲渡できるのはEscrowを初期化した本人のみ
+        if !initializer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let escrow_account = next_account_info(account_info_iter)?;
+        check_escrow_owner(escrow_account, program_id)?;
+        let mut escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        if escrow_info.initializer_pubkey != *initializer.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // 新しいイニシャライザーの受け取り口座は、要求するミントのトークンアカウントでなければならない
+        let new_token_to_receive_account = next_account_info(account_info_iter)?;
+        if *new_token_to_receive_account.owner != spl_token::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let new_token_to_receive_account_info =
+            TokenAccount::unpack(&new_token_to_receive_account.try_borrow_data()?)?;
+        if new_token_to_receive_account_info.mint != escrow_info.expected_mint
+            || new_token_to_receive_account_info.owner != new_initializer
+        {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // PDAはイニシャライザーの公開鍵から導出するため、一時アカウントも新しいイニシャライザーのPDAに引き渡す
+        let pdas_temp_token_account = next_account_info(account_info_iter)?;
+        if escrow_info.temp_token_account_pubkey != *pdas_temp_token_account.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let token_program = next_account_info(account_info_iter)?;
+        if *token_program.key != spl_token::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let pda_account = next_account_info(account_info_iter)?;
+        let pda = escrow_info.pda(program_id)?;
+        let (new_pda, new_bump_seed) = find_escrow_pda(program_id, &new_initializer, escrow_info.seed_bytes());
+
+        let owner_change_ix = spl_token::instruction::set_authority(
+            token_program.key,
+            pdas_temp_token_account.key,
+            Some(&new_pda),
+            spl_token::instruction::AuthorityType::AccountOwner,
+            &pda,
+            &[&pda],
+        )?;
+        msg!("Calling the token program to hand the temp account to the new pda...");
+        invoke_signed(
+            &owner_change_ix,
+            &[
+                pdas_temp_token_account.clone(),
+                pda_account.clone(),
+                token_program.clone(),
+            ],
+            &[&[&b"escrow"[..], escrow_info.initializer_pubkey.as_ref(), escrow_info.seed_bytes(), &[escrow_info.bump]]],
+        )?;
+
+        escrow_info.initializer_pubkey = new_initializer;
+        escrow_info.initializer_token_to_receive_account_pubkey = *new_token_to_receive_account.key;
+        escrow_info.bump = new_bump_seed;
+        Escrow::pack(
+            escrow_info,
+            &mut escrow_account.try_borrow_mut_data()?
+        )?;
+
+        msg!("Escrow transferred from {} to {}", initializer.key, new_initializer);
+        Ok(())
+    }
+
+    fn process_describe(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let escrow_account = next_account_info(account_info_iter)?;
+        check_escrow_owner(escrow_account, program_id)?;
+
+        // 未初期化のEscrowはunpackがUninitializedAccountで拒否する
+        let escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+        msg!("{}", escrow_info);
+        Ok(())
+    }
+}
@@ -5,13 +5,99 @@ use solana_program::{
 };
 
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use std::{convert::TryFrom, fmt};
 
+use crate::error::EscrowError;
+
+/// maximum length of the maker's memo in bytes
+pub const MEMO_LEN: usize = 64;
+
+/// maximum length of the seed the escrow's PDA is derived from, in bytes
+pub const MAX_SEED_LEN: usize = 16;
+
+/// an escrow with fewer tokens than this left is dust, its initializer may reclaim them
+pub const MIN_FILL: u64 = 10;
+
+#[derive(Debug, PartialEq)]
 pub struct Escrow {
     pub is_initialized: bool,
     pub initializer_pubkey: Pubkey,
     pub temp_token_account_pubkey: Pubkey,
     pub initializer_token_to_receive_account_pubkey: Pubkey,
     pub expected_amount: u64,
+    pub memo: [u8; MEMO_LEN],
+    pub expected_mint: Pubkey,
+    pub unlock_timestamp: i64,
+    pub remaining_amount: u64,
+    pub seed_len: u8,
+    pub seed: [u8; MAX_SEED_LEN],
+    pub bump: u8,
+}
+
+impl Escrow {
+    /// the memo up to its zero padding
+    pub fn memo_bytes(&self) -> &[u8] {
+        let len = self.memo.iter().position(|b| *b == 0).unwrap_or(MEMO_LEN);
+        &self.memo[..len]
+    }
+
+    /// the seed up to its length, the PDA is derived from `[b"escrow", initializer, seed]`
+    pub fn seed_bytes(&self) -> &[u8] {
+        &self.seed[..self.seed_len as usize]
+    }
+
+    /// the PDA from the stored seed and bump, without searching for the bump again
+    pub fn pda(&self, program_id: &Pubkey) -> Result<Pubkey, ProgramError> {
+        Pubkey::create_program_address(
+            &[b"escrow", self.initializer_pubkey.as_ref(), self.seed_bytes(), &[self.bump]],
+            program_id,
+        )
+            .map_err(|_| ProgramError::InvalidSeeds)
+    }
+
+    /// reads only the leading is_initialized byte, without unpacking the rest of the escrow
+    pub fn is_account_initialized(data: &[u8]) -> Result<bool, ProgramError> {
+        if data.len() != Escrow::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        match data[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+/// the PDA owning the temp token accounts of `initializer`'s escrows initialized with `seed`,
+/// two initializers picking the same seed never share it
+pub fn find_escrow_pda(program_id: &Pubkey, initializer: &Pubkey, seed: &[u8]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"escrow", initializer.as_ref(), seed], program_id)
+}
+
+/// sum of the tokens still offered by the given escrows, for off-chain analytics
+pub fn total_value_locked(escrows: &[Escrow]) -> Result<u64, EscrowError> {
+    let remaining_amounts: Vec<u64> = escrows.iter().map(|escrow| escrow.remaining_amount).collect();
+    u64::try_from(to_u128_sum(&remaining_amounts)).map_err(|_| EscrowError::AmountOverflow)
+}
+
+/// sums token amounts without overflowing, up to 2^64 of them always fit in a u128
+pub fn to_u128_sum(values: &[u64]) -> u128 {
+    values.iter().map(|value| *value as u128).sum()
+}
+
+/// one line summary for logs and failing tests, pubkeys in base58
+impl fmt::Display for Escrow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Escrow {{ initialized: {}, initializer: {}, temp_token_account: {}, token_to_receive_account: {}, expected_amount: {} }}",
+            self.is_initialized,
+            self.initializer_pubkey,
+            self.temp_token_account_pubkey,
+            self.initializer_token_to_receive_account_pubkey,
+            self.expected_amount
+        )
+    }
 }
 
 impl Sealed for Escrow { }
@@ -23,22 +109,36 @@ impl IsInitialized for Escrow {
 }
 
 impl Pack for Escrow {
-    const LEN: usize = 105;
+    const LEN: usize = 235;
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        // array_ref! panics on a short buffer
+        if src.len() < Escrow::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
         let src = array_ref![src, 0, Escrow::LEN];
         let (
             is_initialized,
             initializer_pubkey,
             temp_token_account_pubkey,
             initializer_token_to_receive_account_pubkey,
-            expected_amount
-        ) = array_refs![src, 1, 32, 32, 32, 8];
+            expected_amount,
+            memo,
+            expected_mint,
+            unlock_timestamp,
+            remaining_amount,
+            seed_len,
+            seed,
+            bump,
+        ) = array_refs![src, 1, 32, 32, 32, 8, MEMO_LEN, 32, 8, 8, 1, MAX_SEED_LEN, 1];
 
         let is_initialized = match is_initialized {
             [0] => false,
             [1] => true,
             _ => return Err(ProgramError::InvalidAccountData),
         };
+        if seed_len[0] as usize > MAX_SEED_LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
 
         Ok(Escrow {
             is_initialized,
@@ -46,6 +146,13 @@ impl Pack for Escrow {
             temp_token_account_pubkey: Pubkey::new_from_array(*temp_token_account_pubkey),
             initializer_token_to_receive_account_pubkey: Pubkey::new_from_array(*initializer_token_to_receive_account_pubkey),
             expected_amount: u64::from_le_bytes(*expected_amount),
+            memo: *memo,
+            expected_mint: Pubkey::new_from_array(*expected_mint),
+            unlock_timestamp: i64::from_le_bytes(*unlock_timestamp),
+            remaining_amount: u64::from_le_bytes(*remaining_amount),
+            seed_len: seed_len[0],
+            seed: *seed,
+            bump: bump[0],
         })
     }
 
@@ -57,14 +164,28 @@ impl Pack for Escrow {
             temp_token_account_pubkey_dst,
             initializer_token_to_receive_account_pubkey_dst,
             expected_amount_dst,
-        ) = mut_array_refs![dst, 1, 32, 32, 32, 8];
+            memo_dst,
+            expected_mint_dst,
+            unlock_timestamp_dst,
+            remaining_amount_dst,
+            seed_len_dst,
+            seed_dst,
+            bump_dst,
+        ) = mut_array_refs![dst, 1, 32, 32, 32, 8, MEMO_LEN, 32, 8, 8, 1, MAX_SEED_LEN, 1];
 
         let Escrow {
             is_initialized,
             initializer_pubkey,
             temp_token_account_pubkey,
             initializer_token_to_receive_account_pubkey,
-            expected_amount
+            expected_amount,
+            memo,
+            expected_mint,
+            unlock_timestamp,
+            remaining_amount,
+            seed_len,
+            seed,
+            bump,
         } = self;
 
         is_initialized_dst[0] = *is_initialized as u8;
@@ -78,5 +199,143 @@ impl Pack for Escrow {
             initializer_token_to_receive_account_pubkey.as_ref()
         );
         *expected_amount_dst = expected_amount.to_le_bytes();
+        *memo_dst = *memo;
+        expected_mint_dst.copy_from_slice(
+            expected_mint.as_ref()
+        );
+        *unlock_timestamp_dst = unlock_timestamp.to_le_bytes();
+        *remaining_amount_dst = remaining_amount.to_le_bytes();
+        seed_len_dst[0] = *seed_len;
+        *seed_dst = *seed;
+        bump_dst[0] = *bump;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_len_matches_field_sizes() {
+        // is_initialized, 3 pubkeys, expected_amount, memo, expected_mint,
+        // unlock_timestamp, remaining_amount, seed_len, seed, bump
+        assert_eq!(Escrow::LEN, 1 + 32 * 3 + 8 + MEMO_LEN + 32 + 8 + 8 + 1 + MAX_SEED_LEN + 1);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_pack_round_trip() {
+        let mut memo = [0; MEMO_LEN];
+        memo[..4].copy_from_slice(b"memo");
+        let mut seed = [0; MAX_SEED_LEN];
+        seed[..5].copy_from_slice(b"offer");
+        let escrow = Escrow {
+            is_initialized: true,
+            initializer_pubkey: Pubkey::new_unique(),
+            temp_token_account_pubkey: Pubkey::new_unique(),
+            initializer_token_to_receive_account_pubkey: Pubkey::new_unique(),
+            expected_amount: u64::MAX,
+            memo,
+            expected_mint: Pubkey::new_unique(),
+            unlock_timestamp: i64::MIN,
+            remaining_amount: 42,
+            seed_len: 5,
+            seed,
+            bump: 254,
+        };
+
+        let mut packed = [0u8; Escrow::LEN];
+        escrow.pack_into_slice(&mut packed);
+        assert_eq!(Escrow::unpack_from_slice(&packed).unwrap(), escrow);
+    }
+
+    #[test]
+    fn test_unpack_rejects_truncated_buffer() {
+        let packed = [0u8; Escrow::LEN];
+        for len in [0, 1, Escrow::LEN - 1] {
+            assert_eq!(
+                Escrow::unpack_from_slice(&packed[..len]),
+                Err(ProgramError::InvalidAccountData)
+            );
+            assert_eq!(
+                Escrow::unpack_unchecked(&packed[..len]),
+                Err(ProgramError::InvalidAccountData)
+            );
+        }
+    }
+
+    #[test]
+    fn test_unpack_rejects_uninitialized_escrow() {
+        let packed = [0u8; Escrow::LEN];
+        assert_eq!(Escrow::unpack(&packed), Err(ProgramError::UninitializedAccount));
+        assert!(!Escrow::unpack_unchecked(&packed).unwrap().is_initialized());
+    }
+
+    #[test]
+    fn test_display_uninitialized_escrow() {
+        let escrow = Escrow::unpack_unchecked(&[0u8; Escrow::LEN]).unwrap();
+        let default_pubkey = Pubkey::default().to_string();
+        assert_eq!(
+            escrow.to_string(),
+            format!(
+                "Escrow {{ initialized: false, initializer: {0}, temp_token_account: {0}, token_to_receive_account: {0}, expected_amount: 0 }}",
+                default_pubkey
+            )
+        );
+    }
+
+    fn escrow_offering(remaining_amount: u64) -> Escrow {
+        Escrow {
+            is_initialized: true,
+            initializer_pubkey: Pubkey::new_unique(),
+            temp_token_account_pubkey: Pubkey::new_unique(),
+            initializer_token_to_receive_account_pubkey: Pubkey::new_unique(),
+            expected_amount: 1000,
+            memo: [0; MEMO_LEN],
+            expected_mint: Pubkey::new_unique(),
+            unlock_timestamp: i64::MAX,
+            remaining_amount,
+            seed_len: 0,
+            seed: [0; MAX_SEED_LEN],
+            bump: 255,
+        }
+    }
+
+    #[test]
+    fn test_total_value_locked() {
+        assert_eq!(total_value_locked(&[]).unwrap(), 0);
+
+        let escrows = [escrow_offering(100), escrow_offering(40), escrow_offering(60)];
+        assert_eq!(total_value_locked(&escrows).unwrap(), 200);
+    }
+
+    #[test]
+    fn test_total_value_locked_overflow() {
+        let at_limit = [escrow_offering(u64::MAX - 1), escrow_offering(1)];
+        assert_eq!(total_value_locked(&at_limit).unwrap(), u64::MAX);
+
+        let past_limit = [escrow_offering(u64::MAX), escrow_offering(1)];
+        assert!(matches!(total_value_locked(&past_limit), Err(EscrowError::AmountOverflow)));
+    }
+
+    #[test]
+    fn test_to_u128_sum() {
+        assert_eq!(to_u128_sum(&[]), 0);
+        assert_eq!(to_u128_sum(&[1, 2, 3]), 6);
+        // would wrap around as a u64
+        assert_eq!(to_u128_sum(&[u64::MAX, u64::MAX, 2]), 2 * u64::MAX as u128 + 2);
+    }
+
+    #[test]
+    fn test_is_account_initialized() {
+        let mut data = vec![0; Escrow::LEN];
+        escrow_offering(100).pack_into_slice(&mut data);
+        assert_eq!(Escrow::is_account_initialized(&data), Ok(true));
+
+        let mut uninitialized = escrow_offering(100);
+        uninitialized.is_initialized = false;
+        uninitialized.pack_into_slice(&mut data);
+        assert_eq!(Escrow::is_account_initialized(&data), Ok(false));
+
+        assert_eq!(Escrow::is_account_initialized(&data[1..]), Err(ProgramError::InvalidAccountData));
+    }
+}
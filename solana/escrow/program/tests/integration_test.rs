@@ -0,0 +1,1328 @@
+mod program_test;
+#[cfg(test)]
+mod test {
+    use super::*;
+    use program_test::{
+        assert_account_count, cancel_escrow_instruction, deposit_instruction, describe_escrow_instruction, exchange_instruction, get_token_balance, init_escrow_instruction,
+        init_escrow_instruction_with_seed, initialize_mint, initialize_token_account, mint_some, open_escrow, open_escrow_with, padded_seed,
+        reclaim_dust_instruction, transfer_ownership_instruction, AddPacked, EscrowProgramTest, DEFAULT_SEED,
+    };
+    use solana_escrow::{error::EscrowError, instruction::EscrowInstruction, state::{find_escrow_pda, Escrow, MEMO_LEN}, VERSION};
+    use solana_program::{account_info::AccountInfo, entrypoint::ProgramResult, program_pack::Pack, rent::Rent};
+    use solana_program_test::{processor, tokio, ProgramTestBanksClientExt};
+    use solana_sdk::{
+        account::{AccountSharedData, WritableAccount},
+        instruction::{AccountMeta, Instruction, InstructionError},
+        pubkey::Pubkey,
+        signature::{Keypair, Signer},
+        system_program,
+        transaction::TransactionError,
+    };
+
+    #[tokio::test]
+    async fn test_cancel_escrow() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        pt.process_tx_and_assert_ok(&[open.cancel_escrow_instruction(&pt.program_id)], &[&open.initializer]).await;
+
+        assert_eq!(get_token_balance(open.initializer_x_account, &mut pt).await, 100);
+        assert!(pt.context.banks_client.get_account(open.temp_x_account).await.unwrap().is_none());
+        assert!(pt.context.banks_client.get_account(open.escrow_account).await.unwrap().is_none());
+    }
+
+    // stands in for the token program, accepting whatever it is asked to do
+    fn fake_token_program(_program_id: &Pubkey, _accounts: &[AccountInfo], _instruction_data: &[u8]) -> ProgramResult {
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cancel_escrow_rejects_fake_token_program() {
+        let fake_token_program_id = Pubkey::new_unique();
+        let mut pt = EscrowProgramTest::start_new_with(|pt, _program_id| {
+            pt.add_program("fake_token_program", fake_token_program_id, processor!(fake_token_program));
+        })
+        .await;
+        let open = open_escrow(&mut pt).await;
+
+        // the fake program would pretend to return the tokens while the escrow gets closed
+        let mut cancel_ix = open.cancel_escrow_instruction(&pt.program_id);
+        cancel_ix.accounts[4].pubkey = fake_token_program_id;
+        pt.process_tx_and_assert_err(
+            &[cancel_ix],
+            &[&open.initializer],
+            TransactionError::InstructionError(0, InstructionError::IncorrectProgramId),
+        ).await;
+
+        assert_eq!(get_token_balance(open.temp_x_account, &mut pt).await, 100);
+        assert_eq!(get_token_balance(open.initializer_x_account, &mut pt).await, 0);
+        assert!(pt.context.banks_client.get_account(open.escrow_account).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_init_escrow_with_memo() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow_with(&mut pt, i64::MAX, b"first come first served").await;
+
+        let escrow_info = pt.get_escrow(open.escrow_account).await;
+        assert!(escrow_info.to_string().contains(&format!("initializer: {}", open.initializer.pubkey())));
+        assert_eq!(escrow_info.memo_bytes(), b"first come first served");
+    }
+
+    #[tokio::test]
+    async fn test_exchange_rejects_temp_account_not_owned_by_pda() {
+        let mut pt = EscrowProgramTest::start_new().await;
+
+        let initializer = Keypair::new();
+        let taker = Keypair::new();
+        let mint_x = Keypair::new();
+        let mint_y = Keypair::new();
+        initialize_mint(&mint_x, 0, &mut pt).await;
+        initialize_mint(&mint_y, 0, &mut pt).await;
+
+        let initializer_y_account = initialize_token_account(&initializer.pubkey(), &mint_y.pubkey(), &mut pt).await;
+        let taker_x_account = initialize_token_account(&taker.pubkey(), &mint_x.pubkey(), &mut pt).await;
+        let taker_y_account = initialize_token_account(&taker.pubkey(), &mint_y.pubkey(), &mut pt).await;
+        mint_some(&taker_y_account, &mint_y.pubkey(), &mut pt, 1000).await;
+
+        // the temp account is still owned by the initializer, not the PDA
+        let temp_x_account = initialize_token_account(&initializer.pubkey(), &mint_x.pubkey(), &mut pt).await;
+        mint_some(&temp_x_account, &mint_x.pubkey(), &mut pt, 100).await;
+
+        let escrow_account = Pubkey::new_unique();
+        pt.write_escrow(&escrow_account, &Escrow {
+            is_initialized: true,
+            initializer_pubkey: initializer.pubkey(),
+            temp_token_account_pubkey: temp_x_account,
+            initializer_token_to_receive_account_pubkey: initializer_y_account,
+            expected_amount: 1000,
+            memo: [0; MEMO_LEN],
+            expected_mint: mint_y.pubkey(),
+            unlock_timestamp: i64::MAX,
+            remaining_amount: 100,
+            seed_len: DEFAULT_SEED.len() as u8,
+            seed: padded_seed(DEFAULT_SEED),
+            bump: find_escrow_pda(&pt.program_id, &initializer.pubkey(), DEFAULT_SEED).1,
+        });
+
+        let pda = pt.pda(&initializer.pubkey());
+        pt.process_tx_and_assert_err(&[
+            exchange_instruction(
+                &pt.program_id,
+                &taker.pubkey(),
+                &taker_y_account,
+                &taker_x_account,
+                &temp_x_account,
+                &initializer.pubkey(),
+                &initializer_y_account,
+                &escrow_account,
+                &pda,
+                &mint_y.pubkey(),
+                &mint_x.pubkey(),
+                100,
+            )
+        ], &[&taker], TransactionError::InstructionError(0, InstructionError::InvalidAccountData)).await;
+
+        assert_eq!(get_token_balance(temp_x_account, &mut pt).await, 100);
+    }
+
+    #[tokio::test]
+    async fn test_init_escrow_rejects_zero_amount() {
+        let mut pt = EscrowProgramTest::start_new().await;
+
+        let initializer = Keypair::new();
+        let mint_x = Keypair::new();
+        let mint_y = Keypair::new();
+        initialize_mint(&mint_x, 0, &mut pt).await;
+        initialize_mint(&mint_y, 0, &mut pt).await;
+
+        let initializer_y_account = initialize_token_account(&initializer.pubkey(), &mint_y.pubkey(), &mut pt).await;
+        let temp_x_account = initialize_token_account(&initializer.pubkey(), &mint_x.pubkey(), &mut pt).await;
+        mint_some(&temp_x_account, &mint_x.pubkey(), &mut pt, 100).await;
+
+        let escrow_account = pt.create_escrow_account().await;
+        pt.process_tx_and_assert_err(&[
+            init_escrow_instruction(
+                &pt.program_id,
+                &initializer.pubkey(),
+                &temp_x_account,
+                &initializer_y_account,
+                &escrow_account.pubkey(),
+                0,
+                i64::MAX,
+                &[],
+            )
+        ], &[&initializer], TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(EscrowError::InvalidAmount as u32),
+        )).await;
+    }
+
+    #[tokio::test]
+    async fn test_exchange_rejects_wrong_mint() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        // the taker tries to pay with token Z instead of the expected token Y
+        let mint_z = Keypair::new();
+        initialize_mint(&mint_z, 0, &mut pt).await;
+        let taker_z_account = initialize_token_account(&open.taker.pubkey(), &mint_z.pubkey(), &mut pt).await;
+        mint_some(&taker_z_account, &mint_z.pubkey(), &mut pt, 1000).await;
+
+        let mut exchange_ix = open.exchange_instruction(&pt.program_id, 100);
+        exchange_ix.accounts[1].pubkey = taker_z_account;
+        pt.process_tx_and_assert_err(
+            &[exchange_ix],
+            &[&open.taker],
+            TransactionError::InstructionError(0, InstructionError::InvalidAccountData),
+        ).await;
+    }
+
+    #[tokio::test]
+    async fn test_exchange_rejects_empty_temp_account() {
+        let mut pt = EscrowProgramTest::start_new().await;
+
+        let initializer = Keypair::new();
+        let taker = Keypair::new();
+        let mint_x = Keypair::new();
+        let mint_y = Keypair::new();
+        initialize_mint(&mint_x, 0, &mut pt).await;
+        initialize_mint(&mint_y, 0, &mut pt).await;
+
+        let initializer_y_account = initialize_token_account(&initializer.pubkey(), &mint_y.pubkey(), &mut pt).await;
+        // nothing is ever minted into the temp account
+        let temp_x_account = initialize_token_account(&initializer.pubkey(), &mint_x.pubkey(), &mut pt).await;
+
+        let taker_x_account = initialize_token_account(&taker.pubkey(), &mint_x.pubkey(), &mut pt).await;
+        let taker_y_account = initialize_token_account(&taker.pubkey(), &mint_y.pubkey(), &mut pt).await;
+        mint_some(&taker_y_account, &mint_y.pubkey(), &mut pt, 1000).await;
+
+        let escrow_account = pt.create_escrow_account().await;
+        pt.process_tx_and_assert_ok(&[
+            init_escrow_instruction(
+                &pt.program_id,
+                &initializer.pubkey(),
+                &temp_x_account,
+                &initializer_y_account,
+                &escrow_account.pubkey(),
+                1000,
+                i64::MAX,
+                &[],
+            )
+        ], &[&initializer]).await;
+
+        let pda = pt.pda(&initializer.pubkey());
+        pt.process_tx_and_assert_err(&[
+            exchange_instruction(
+                &pt.program_id,
+                &taker.pubkey(),
+                &taker_y_account,
+                &taker_x_account,
+                &temp_x_account,
+                &initializer.pubkey(),
+                &initializer_y_account,
+                &escrow_account.pubkey(),
+                &pda,
+                &mint_y.pubkey(),
+                &mint_x.pubkey(),
+                0,
+            )
+        ], &[&taker], TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(EscrowError::AmountTooSmall as u32),
+        )).await;
+    }
+
+    #[tokio::test]
+    async fn test_exchange_rejects_expired_escrow() {
+        let mut pt = EscrowProgramTest::start_new().await;
+
+        let deadline = pt.get_clock().await.unix_timestamp + 60;
+        let open = open_escrow_with(&mut pt, deadline, &[]).await;
+
+        pt.set_unix_timestamp(deadline + 1).await;
+
+        pt.process_tx_and_assert_err(&[open.exchange_instruction(&pt.program_id, 100)], &[&open.taker], TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(EscrowError::EscrowExpired as u32),
+        )).await;
+    }
+
+    #[test]
+    fn test_instruction_builders_account_count() {
+        let program_id = Pubkey::new_unique();
+
+        let init_ix = init_escrow_instruction(
+            &program_id,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            1000,
+            i64::MAX,
+            &[],
+        );
+        assert_account_count(&init_ix.accounts, 6);
+
+        let exchange_ix = exchange_instruction(
+            &program_id,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            100,
+        );
+        assert_account_count(&exchange_ix.accounts, 12);
+
+        let cancel_ix = cancel_escrow_instruction(
+            &program_id,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+        assert_account_count(&cancel_ix.accounts, 6);
+    }
+
+    #[tokio::test]
+    async fn test_exchange_partial_fills() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        // the first fill takes 40 of 100 tokens and pays 40% of the expected 1000
+        pt.process_tx_and_assert_ok(&[open.exchange_instruction(&pt.program_id, 40)], &[&open.taker]).await;
+        assert_eq!(get_token_balance(open.taker_x_account, &mut pt).await, 40);
+        assert_eq!(get_token_balance(open.initializer_y_account, &mut pt).await, 400);
+
+        let escrow_info = pt.get_escrow(open.escrow_account).await;
+        assert_eq!(escrow_info.remaining_amount, 60);
+        assert_eq!(escrow_info.expected_amount, 600);
+
+        // the second fill completes the trade and closes the accounts
+        pt.process_tx_and_assert_ok(&[open.exchange_instruction(&pt.program_id, 60)], &[&open.taker]).await;
+        assert_eq!(get_token_balance(open.taker_x_account, &mut pt).await, 100);
+        assert_eq!(get_token_balance(open.taker_y_account, &mut pt).await, 0);
+        assert_eq!(get_token_balance(open.initializer_y_account, &mut pt).await, 1000);
+        assert!(pt.context.banks_client.get_account(open.temp_x_account).await.unwrap().is_none());
+        assert!(pt.context.banks_client.get_account(open.escrow_account).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_final_fill_sweeps_donated_tokens() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        // anyone can send tokens to the temp account, which would leave a balance behind
+        // and make closing it fail on the final fill
+        mint_some(&open.temp_x_account, &open.mint_x.pubkey(), &mut pt, 1).await;
+
+        pt.process_tx_and_assert_ok(&[open.exchange_instruction(&pt.program_id, 40)], &[&open.taker]).await;
+        assert_eq!(get_token_balance(open.taker_x_account, &mut pt).await, 40);
+        assert_eq!(get_token_balance(open.temp_x_account, &mut pt).await, 61);
+
+        // the final fill hands the surplus to the taker along with the rest
+        pt.process_tx_and_assert_ok(&[open.exchange_instruction(&pt.program_id, 60)], &[&open.taker]).await;
+        assert_eq!(get_token_balance(open.taker_x_account, &mut pt).await, 101);
+        assert_eq!(get_token_balance(open.initializer_y_account, &mut pt).await, 1000);
+        assert!(pt.context.banks_client.get_account(open.temp_x_account).await.unwrap().is_none());
+        assert!(pt.context.banks_client.get_account(open.escrow_account).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_init_escrow_rejects_reinitialization() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        let other_temp_x_account = initialize_token_account(&open.initializer.pubkey(), &open.mint_x.pubkey(), &mut pt).await;
+        mint_some(&other_temp_x_account, &open.mint_x.pubkey(), &mut pt, 50).await;
+        let escrow_data_before = pt.context.banks_client.get_account(open.escrow_account).await.unwrap().unwrap().data;
+
+        pt.process_tx_and_assert_err(&[
+            init_escrow_instruction(
+                &pt.program_id,
+                &open.initializer.pubkey(),
+                &other_temp_x_account,
+                &open.initializer_y_account,
+                &open.escrow_account,
+                500,
+                i64::MAX,
+                &[],
+            )
+        ], &[&open.initializer], TransactionError::InstructionError(0, InstructionError::AccountAlreadyInitialized)).await;
+
+        let escrow_data_after = pt.context.banks_client.get_account(open.escrow_account).await.unwrap().unwrap().data;
+        assert_eq!(escrow_data_before, escrow_data_after);
+        assert_eq!(Escrow::unpack(&escrow_data_after).unwrap().temp_token_account_pubkey, open.temp_x_account);
+    }
+
+    #[tokio::test]
+    async fn test_version_returns_version_constant() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let program_id = pt.program_id;
+
+        pt.with_simulation(&[Instruction::new_with_bytes(program_id, &[3], vec![])], &[], |simulation| {
+            assert_eq!(simulation.result, Some(Ok(())));
+            assert!(simulation.logs.contains(&"Program log: Instruction: Version".to_string()));
+
+            let return_data = simulation.return_data.expect("return data");
+            assert_eq!(return_data.program_id, program_id);
+            // trailing zero bytes are trimmed off return data, so VERSION = 1 comes back as [1]
+            let mut version = [0u8; 4];
+            version[..return_data.data.len()].copy_from_slice(&return_data.data);
+            assert_eq!(u32::from_le_bytes(version), VERSION);
+        }).await;
+    }
+
+    #[tokio::test]
+    async fn test_init_and_exchange_emit_structured_logs() {
+        let mut pt = EscrowProgramTest::start_new().await;
+
+        let initializer = Keypair::new();
+        let taker = Keypair::new();
+        let mint_x = Keypair::new();
+        let mint_y = Keypair::new();
+        initialize_mint(&mint_x, 0, &mut pt).await;
+        initialize_mint(&mint_y, 0, &mut pt).await;
+
+        let initializer_y_account = initialize_token_account(&initializer.pubkey(), &mint_y.pubkey(), &mut pt).await;
+        let temp_x_account = initialize_token_account(&initializer.pubkey(), &mint_x.pubkey(), &mut pt).await;
+        mint_some(&temp_x_account, &mint_x.pubkey(), &mut pt, 100).await;
+
+        let taker_x_account = initialize_token_account(&taker.pubkey(), &mint_x.pubkey(), &mut pt).await;
+        let taker_y_account = initialize_token_account(&taker.pubkey(), &mint_y.pubkey(), &mut pt).await;
+        mint_some(&taker_y_account, &mint_y.pubkey(), &mut pt, 1000).await;
+
+        let escrow_account = pt.create_escrow_account().await;
+        let init_logs = pt.process_tx_and_return_logs(&[
+            init_escrow_instruction(
+                &pt.program_id,
+                &initializer.pubkey(),
+                &temp_x_account,
+                &initializer_y_account,
+                &escrow_account.pubkey(),
+                1000,
+                i64::MAX,
+                &[],
+            )
+        ], &[&initializer]).await;
+        let expected_init_log = format!(
+            "Program log: ESCROW_INIT initializer={} expected=1000 temp={}",
+            initializer.pubkey(),
+            temp_x_account
+        );
+        assert!(init_logs.contains(&expected_init_log), "{:?}", init_logs);
+
+        let pda = pt.pda(&initializer.pubkey());
+        let exchange_logs = pt.process_tx_and_return_logs(&[
+            exchange_instruction(
+                &pt.program_id,
+                &taker.pubkey(),
+                &taker_y_account,
+                &taker_x_account,
+                &temp_x_account,
+                &initializer.pubkey(),
+                &initializer_y_account,
+                &escrow_account.pubkey(),
+                &pda,
+                &mint_y.pubkey(),
+                &mint_x.pubkey(),
+                100,
+            )
+        ], &[&taker]).await;
+        let expected_filled_log = format!("Program log: ESCROW_FILLED taker={} amount=100", taker.pubkey());
+        assert!(exchange_logs.contains(&expected_filled_log), "{:?}", exchange_logs);
+    }
+
+    #[tokio::test]
+    async fn test_exchange_rejects_receive_account_with_wrong_mint() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        // the taker wants the escrowed X tokens sent to their Y account
+        let taker_other_y_account = initialize_token_account(&open.taker.pubkey(), &open.mint_y.pubkey(), &mut pt).await;
+        let mut exchange_ix = open.exchange_instruction(&pt.program_id, 100);
+        exchange_ix.accounts[2].pubkey = taker_other_y_account;
+
+        let (result, logs) = pt.process_tx_capturing_logs(&[exchange_ix], &[&open.taker]).await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidAccountData)
+        );
+
+        let expected_log = format!(
+            "Program log: Taker's receive account mint {} does not match the escrowed mint {}",
+            open.mint_y.pubkey(),
+            open.mint_x.pubkey()
+        );
+        assert!(logs.contains(&expected_log), "{:?}", logs);
+        assert_eq!(get_token_balance(open.temp_x_account, &mut pt).await, 100);
+    }
+
+    #[tokio::test]
+    async fn test_init_escrow_account_bytes() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow_with(&mut pt, 1_700_000_000, b"memo").await;
+
+        let mut memo = [0; MEMO_LEN];
+        memo[..4].copy_from_slice(b"memo");
+        let mut expected = vec![0; Escrow::LEN];
+        Escrow {
+            is_initialized: true,
+            initializer_pubkey: open.initializer.pubkey(),
+            temp_token_account_pubkey: open.temp_x_account,
+            initializer_token_to_receive_account_pubkey: open.initializer_y_account,
+            expected_amount: 1000,
+            memo,
+            expected_mint: open.mint_y.pubkey(),
+            unlock_timestamp: 1_700_000_000,
+            remaining_amount: 100,
+            seed_len: DEFAULT_SEED.len() as u8,
+            seed: padded_seed(DEFAULT_SEED),
+            bump: find_escrow_pda(&pt.program_id, &open.initializer.pubkey(), DEFAULT_SEED).1,
+        }.pack_into_slice(&mut expected);
+
+        pt.assert_account_data_eq(open.escrow_account, &expected).await;
+    }
+
+    #[tokio::test]
+    async fn test_transfer_ownership_pays_new_initializer() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        let new_initializer = Keypair::new();
+        let new_initializer_y_account = initialize_token_account(&new_initializer.pubkey(), &open.mint_y.pubkey(), &mut pt).await;
+
+        pt.process_tx_and_assert_ok(&[
+            transfer_ownership_instruction(
+                &pt.program_id,
+                &open.initializer.pubkey(),
+                &open.escrow_account,
+                &new_initializer.pubkey(),
+                &new_initializer_y_account,
+                &open.temp_x_account,
+                &open.pda,
+            )
+        ], &[&open.initializer]).await;
+
+        // the temp account follows the escrow to the new initializer's PDA
+        let new_pda = pt.pda(&new_initializer.pubkey());
+        let temp_account = pt.context.banks_client.get_account(open.temp_x_account).await.unwrap().unwrap();
+        assert_eq!(spl_token::state::Account::unpack(&temp_account.data).unwrap().owner, new_pda);
+
+        let mut exchange_ix = open.exchange_instruction(&pt.program_id, 100);
+        exchange_ix.accounts[4].pubkey = new_initializer.pubkey();
+        exchange_ix.accounts[5].pubkey = new_initializer_y_account;
+        exchange_ix.accounts[8].pubkey = new_pda;
+        pt.process_tx_and_assert_ok(&[exchange_ix], &[&open.taker]).await;
+
+        assert_eq!(get_token_balance(new_initializer_y_account, &mut pt).await, 1000);
+        assert_eq!(get_token_balance(open.initializer_y_account, &mut pt).await, 0);
+        assert_eq!(get_token_balance(open.taker_x_account, &mut pt).await, 100);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_ownership_requires_current_initializer() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        let impostor = Keypair::new();
+        let impostor_y_account = initialize_token_account(&impostor.pubkey(), &open.mint_y.pubkey(), &mut pt).await;
+
+        pt.process_tx_and_assert_err(&[
+            transfer_ownership_instruction(
+                &pt.program_id,
+                &impostor.pubkey(),
+                &open.escrow_account,
+                &impostor.pubkey(),
+                &impostor_y_account,
+                &open.temp_x_account,
+                &open.pda,
+            )
+        ], &[&impostor], TransactionError::InstructionError(0, InstructionError::InvalidAccountData)).await;
+    }
+
+    #[tokio::test]
+    async fn test_reclaim_dust_after_partial_fills() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        let reclaim_dust = reclaim_dust_instruction(
+            &pt.program_id,
+            &open.initializer.pubkey(),
+            &open.temp_x_account,
+            &open.initializer_x_account,
+            &open.escrow_account,
+            &open.pda,
+        );
+
+        // nothing has been taken yet, so this is not dust
+        pt.process_tx_and_assert_err(
+            std::slice::from_ref(&reclaim_dust),
+            &[&open.initializer],
+            TransactionError::InstructionError(0, InstructionError::Custom(EscrowError::NotDust as u32)),
+        ).await;
+
+        pt.process_tx_and_assert_ok(&[open.exchange_instruction(&pt.program_id, 60)], &[&open.taker]).await;
+        pt.process_tx_and_assert_ok(&[open.exchange_instruction(&pt.program_id, 35)], &[&open.taker]).await;
+        assert_eq!(get_token_balance(open.temp_x_account, &mut pt).await, 5);
+
+        // the rejected attempt above was this very transaction, it needs a fresh blockhash to be sent again
+        pt.context.last_blockhash = pt.context.banks_client
+            .get_new_latest_blockhash(&pt.context.last_blockhash)
+            .await
+            .unwrap();
+        pt.process_tx_and_assert_ok(&[reclaim_dust], &[&open.initializer]).await;
+
+        assert_eq!(get_token_balance(open.initializer_x_account, &mut pt).await, 5);
+        assert!(pt.context.banks_client.get_account(open.temp_x_account).await.unwrap().is_none());
+        assert!(pt.context.banks_client.get_account(open.escrow_account).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_init_many_escrows() {
+        let mut pt = EscrowProgramTest::start_new().await;
+
+        let initializer = Keypair::new();
+        let mint_x = Keypair::new();
+        let mint_y = Keypair::new();
+        initialize_mint(&mint_x, 0, &mut pt).await;
+        initialize_mint(&mint_y, 0, &mut pt).await;
+        let initializer_y_account = initialize_token_account(&initializer.pubkey(), &mint_y.pubkey(), &mut pt).await;
+
+        let escrow_accounts = pt
+            .init_many_escrows(5, &initializer, &mint_x.pubkey(), &initializer_y_account, 100)
+            .await;
+        assert_eq!(escrow_accounts.len(), 5);
+
+        // BanksClient has no get_program_accounts, so enumerate the returned accounts instead
+        let mut program_accounts = 0;
+        for escrow_account in escrow_accounts {
+            let account = pt.context.banks_client.get_account(escrow_account).await.unwrap().unwrap();
+            if account.owner == pt.program_id && Escrow::unpack(&account.data).is_ok() {
+                program_accounts += 1;
+            }
+        }
+        assert!(program_accounts >= 5);
+    }
+
+    #[tokio::test]
+    async fn test_exchange_rejects_initializer_lamport_overflow() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        // closing the temp account still fits, adding the escrow rent on top overflows by one lamport
+        let temp_rent = pt.rent.minimum_balance(spl_token::state::Account::LEN);
+        let escrow_rent = pt.rent.minimum_balance(Escrow::LEN);
+        let initializer_lamports = u64::MAX - temp_rent - escrow_rent + 1;
+        pt.context.set_account(
+            &open.initializer.pubkey(),
+            &AccountSharedData::new(initializer_lamports, 0, &system_program::id()),
+        );
+
+        pt.process_tx_and_assert_err(&[open.exchange_instruction(&pt.program_id, 100)], &[&open.taker], TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(EscrowError::AmountOverflow as u32),
+        )).await;
+
+        let initializer_account = pt.context.banks_client.get_account(open.initializer.pubkey()).await.unwrap().unwrap();
+        assert_eq!(initializer_account.lamports, initializer_lamports);
+        let escrow = pt.context.banks_client.get_account(open.escrow_account).await.unwrap().unwrap();
+        assert_eq!(escrow.lamports, escrow_rent);
+    }
+
+    #[tokio::test]
+    async fn test_init_escrow_rejects_bogus_token_program() {
+        let mut pt = EscrowProgramTest::start_new().await;
+
+        let initializer = Keypair::new();
+        let mint_x = Keypair::new();
+        let mint_y = Keypair::new();
+        initialize_mint(&mint_x, 0, &mut pt).await;
+        initialize_mint(&mint_y, 0, &mut pt).await;
+
+        let initializer_y_account = initialize_token_account(&initializer.pubkey(), &mint_y.pubkey(), &mut pt).await;
+        let temp_x_account = initialize_token_account(&initializer.pubkey(), &mint_x.pubkey(), &mut pt).await;
+        mint_some(&temp_x_account, &mint_x.pubkey(), &mut pt, 100).await;
+
+        let escrow_account = pt.create_escrow_account().await;
+        let mut init_ix = init_escrow_instruction(
+            &pt.program_id,
+            &initializer.pubkey(),
+            &temp_x_account,
+            &initializer_y_account,
+            &escrow_account.pubkey(),
+            1000,
+            i64::MAX,
+            &[],
+        );
+        init_ix.accounts[5].pubkey = Pubkey::new_unique();
+
+        pt.process_tx_and_assert_err(
+            &[init_ix],
+            &[&initializer],
+            TransactionError::InstructionError(0, InstructionError::IncorrectProgramId),
+        ).await;
+    }
+
+    #[tokio::test]
+    async fn test_exchange_rejects_bogus_token_program() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        let mut exchange_ix = open.exchange_instruction(&pt.program_id, 100);
+        exchange_ix.accounts[7].pubkey = Pubkey::new_unique();
+
+        pt.process_tx_and_assert_err(
+            &[exchange_ix],
+            &[&open.taker],
+            TransactionError::InstructionError(0, InstructionError::IncorrectProgramId),
+        ).await;
+        assert_eq!(get_token_balance(open.taker_y_account, &mut pt).await, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_init_and_exchange_with_preloaded_accounts() {
+        let initializer = Keypair::new();
+        let taker = Keypair::new();
+        let mint_x = Pubkey::new_unique();
+        let mint_y = Pubkey::new_unique();
+        let temp_x_account = Pubkey::new_unique();
+        let initializer_y_account = Pubkey::new_unique();
+        let taker_x_account = Pubkey::new_unique();
+        let taker_y_account = Pubkey::new_unique();
+
+        let rent = Rent::default();
+        let mut pt = EscrowProgramTest::start_new_with(|pt, _program_id| {
+            for (mint, supply) in [(mint_x, 100), (mint_y, 1000)] {
+                pt.add_packable_account(
+                    mint,
+                    rent.minimum_balance(spl_token::state::Mint::LEN),
+                    &spl_token::state::Mint { supply, is_initialized: true, ..Default::default() },
+                    &spl_token::id(),
+                );
+            }
+            for (token_account, mint, owner, amount) in [
+                (temp_x_account, mint_x, initializer.pubkey(), 100),
+                (initializer_y_account, mint_y, initializer.pubkey(), 0),
+                (taker_x_account, mint_x, taker.pubkey(), 0),
+                (taker_y_account, mint_y, taker.pubkey(), 1000),
+            ] {
+                pt.add_packable_account(
+                    token_account,
+                    rent.minimum_balance(spl_token::state::Account::LEN),
+                    &spl_token::state::Account {
+                        mint,
+                        owner,
+                        amount,
+                        state: spl_token::state::AccountState::Initialized,
+                        ..Default::default()
+                    },
+                    &spl_token::id(),
+                );
+            }
+        })
+        .await;
+
+        let escrow_account = pt.create_escrow_account().await;
+        pt.process_tx_and_assert_ok(&[
+            init_escrow_instruction(
+                &pt.program_id,
+                &initializer.pubkey(),
+                &temp_x_account,
+                &initializer_y_account,
+                &escrow_account.pubkey(),
+                1000,
+                i64::MAX,
+                &[],
+            )
+        ], &[&initializer]).await;
+
+        let pda = pt.pda(&initializer.pubkey());
+        pt.process_tx_and_assert_ok(&[
+            exchange_instruction(
+                &pt.program_id,
+                &taker.pubkey(),
+                &taker_y_account,
+                &taker_x_account,
+                &temp_x_account,
+                &initializer.pubkey(),
+                &initializer_y_account,
+                &escrow_account.pubkey(),
+                &pda,
+                &mint_y,
+                &mint_x,
+                100,
+            )
+        ], &[&taker]).await;
+
+        assert_eq!(get_token_balance(taker_x_account, &mut pt).await, 100);
+        assert_eq!(get_token_balance(taker_y_account, &mut pt).await, 0);
+        assert_eq!(get_token_balance(initializer_y_account, &mut pt).await, 1000);
+        assert!(pt.context.banks_client.get_account(temp_x_account).await.unwrap().is_none());
+        assert!(pt.context.banks_client.get_account(escrow_account.pubkey()).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_escrows_with_different_seeds_exchange_independently() {
+        let mut pt = EscrowProgramTest::start_new().await;
+
+        let initializer = Keypair::new();
+        let taker = Keypair::new();
+        let mint_x = Keypair::new();
+        let mint_y = Keypair::new();
+        initialize_mint(&mint_x, 0, &mut pt).await;
+        initialize_mint(&mint_y, 0, &mut pt).await;
+
+        let initializer_y_account = initialize_token_account(&initializer.pubkey(), &mint_y.pubkey(), &mut pt).await;
+        let taker_x_account = initialize_token_account(&taker.pubkey(), &mint_x.pubkey(), &mut pt).await;
+        let taker_y_account = initialize_token_account(&taker.pubkey(), &mint_y.pubkey(), &mut pt).await;
+        mint_some(&taker_y_account, &mint_y.pubkey(), &mut pt, 2000).await;
+
+        let mut escrows = vec![];
+        for seed in [&b"first"[..], &b"second"[..]] {
+            let temp_x_account = initialize_token_account(&initializer.pubkey(), &mint_x.pubkey(), &mut pt).await;
+            mint_some(&temp_x_account, &mint_x.pubkey(), &mut pt, 100).await;
+            let escrow_account = pt.create_escrow_account().await;
+            pt.process_tx_and_assert_ok(&[
+                init_escrow_instruction_with_seed(
+                    &pt.program_id,
+                    &initializer.pubkey(),
+                    &temp_x_account,
+                    &initializer_y_account,
+                    &escrow_account.pubkey(),
+                    1000,
+                    i64::MAX,
+                    seed,
+                    &[],
+                )
+            ], &[&initializer]).await;
+
+            let temp_account = pt.context.banks_client.get_account(temp_x_account).await.unwrap().unwrap();
+            let temp_account_info = spl_token::state::Account::unpack(&temp_account.data).unwrap();
+            assert_eq!(temp_account_info.owner, pt.pda_for_seed(&initializer.pubkey(), seed));
+            escrows.push((seed, temp_x_account, escrow_account.pubkey()));
+        }
+        assert_ne!(pt.pda_for_seed(&initializer.pubkey(), b"first"), pt.pda_for_seed(&initializer.pubkey(), b"second"));
+
+        // both are open at once, each is taken through its own PDA
+        for (i, (seed, temp_x_account, escrow_account)) in escrows.into_iter().enumerate() {
+            let pda = pt.pda_for_seed(&initializer.pubkey(), seed);
+            pt.process_tx_and_assert_ok(&[
+                exchange_instruction(
+                    &pt.program_id,
+                    &taker.pubkey(),
+                    &taker_y_account,
+                    &taker_x_account,
+                    &temp_x_account,
+                    &initializer.pubkey(),
+                    &initializer_y_account,
+                    &escrow_account,
+                    &pda,
+                    &mint_y.pubkey(),
+                    &mint_x.pubkey(),
+                    100,
+                )
+            ], &[&taker]).await;
+
+            assert_eq!(get_token_balance(taker_x_account, &mut pt).await, 100 * (i as u64 + 1));
+            assert_eq!(get_token_balance(initializer_y_account, &mut pt).await, 1000 * (i as u64 + 1));
+            assert!(pt.context.banks_client.get_account(escrow_account).await.unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exchange_logs_token_program_calls_in_order() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        let (result, logs) = pt.process_tx_capturing_logs(&[open.exchange_instruction(&pt.program_id, 100)], &[&open.taker]).await;
+        assert!(result.is_ok(), "{:?}", result);
+
+        let expected = [
+            "Program log: Calling the token program to transfer tokens to the escrow's initializer...",
+            "Program log: Calling the token program to transfer tokens to the taker...",
+            "Program log: Calling the token program to close pda's temp account...",
+        ];
+        let positions: Vec<usize> = expected
+            .iter()
+            .map(|line| logs.iter().position(|log| log == line).unwrap_or_else(|| panic!("missing {:?} in {:#?}", line, logs)))
+            .collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]), "{:#?}", logs);
+    }
+
+    #[tokio::test]
+    async fn test_exchange_uses_checked_transfers() {
+        let mut pt = EscrowProgramTest::start_new().await;
+
+        let initializer = Keypair::new();
+        let taker = Keypair::new();
+        let mint_x = Keypair::new();
+        let mint_y = Keypair::new();
+        initialize_mint(&mint_x, 6, &mut pt).await;
+        initialize_mint(&mint_y, 9, &mut pt).await;
+
+        let initializer_y_account = initialize_token_account(&initializer.pubkey(), &mint_y.pubkey(), &mut pt).await;
+        let temp_x_account = initialize_token_account(&initializer.pubkey(), &mint_x.pubkey(), &mut pt).await;
+        mint_some(&temp_x_account, &mint_x.pubkey(), &mut pt, 100).await;
+
+        let taker_x_account = initialize_token_account(&taker.pubkey(), &mint_x.pubkey(), &mut pt).await;
+        let taker_y_account = initialize_token_account(&taker.pubkey(), &mint_y.pubkey(), &mut pt).await;
+        mint_some(&taker_y_account, &mint_y.pubkey(), &mut pt, 1000).await;
+
+        // the token program refuses a checked transfer quoting the wrong decimals
+        pt.process_tx_and_assert_err(&[
+            spl_token::instruction::transfer_checked(
+                &spl_token::id(),
+                &taker_y_account,
+                &mint_y.pubkey(),
+                &initializer_y_account,
+                &taker.pubkey(),
+                &[],
+                1,
+                6,
+            ).unwrap()
+        ], &[&taker], TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(spl_token::error::TokenError::MintDecimalsMismatch as u32),
+        )).await;
+
+        let escrow_account = pt.create_escrow_account().await;
+        pt.process_tx_and_assert_ok(&[
+            init_escrow_instruction(
+                &pt.program_id,
+                &initializer.pubkey(),
+                &temp_x_account,
+                &initializer_y_account,
+                &escrow_account.pubkey(),
+                1000,
+                i64::MAX,
+                &[],
+            )
+        ], &[&initializer]).await;
+
+        let pda = pt.pda(&initializer.pubkey());
+        let exchange = |sending_mint: &Pubkey, receiving_mint: &Pubkey| exchange_instruction(
+            &pt.program_id,
+            &taker.pubkey(),
+            &taker_y_account,
+            &taker_x_account,
+            &temp_x_account,
+            &initializer.pubkey(),
+            &initializer_y_account,
+            &escrow_account.pubkey(),
+            &pda,
+            sending_mint,
+            receiving_mint,
+            100,
+        );
+
+        // swapped mints would quote the wrong decimals, the escrow rejects them before calling the token program
+        let swapped_mints = exchange(&mint_x.pubkey(), &mint_y.pubkey());
+        let exchange_ix = exchange(&mint_y.pubkey(), &mint_x.pubkey());
+        pt.process_tx_and_assert_err(&[swapped_mints], &[&taker],
+            TransactionError::InstructionError(0, InstructionError::InvalidAccountData)).await;
+
+        pt.process_tx_and_assert_ok(&[exchange_ix], &[&taker]).await;
+        assert_eq!(get_token_balance(taker_x_account, &mut pt).await, 100);
+        assert_eq!(get_token_balance(initializer_y_account, &mut pt).await, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_exchange_stays_within_compute_budget() {
+        // raise this deliberately when process_exchange is meant to do more work
+        const EXCHANGE_COMPUTE_BUDGET: u64 = 60_000;
+
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        let units_consumed = pt.measure_compute_units(&[open.exchange_instruction(&pt.program_id, 100)], &[&open.taker]).await;
+        assert!(
+            units_consumed < EXCHANGE_COMPUTE_BUDGET,
+            "exchange consumed {} compute units, budget is {}",
+            units_consumed,
+            EXCHANGE_COMPUTE_BUDGET
+        );
+
+        // simulating commits nothing, the escrow is still open
+        assert_eq!(get_token_balance(open.temp_x_account, &mut pt).await, 100);
+    }
+
+    #[tokio::test]
+    async fn test_deposit_tops_up_escrow() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        mint_some(&open.initializer_x_account, &open.mint_x.pubkey(), &mut pt, 50).await;
+        mint_some(&open.taker_y_account, &open.mint_y.pubkey(), &mut pt, 500).await;
+
+        pt.process_tx_and_assert_ok(&[
+            deposit_instruction(
+                &pt.program_id,
+                &open.initializer.pubkey(),
+                &open.initializer_x_account,
+                &open.temp_x_account,
+                &open.escrow_account,
+                50,
+                500,
+            )
+        ], &[&open.initializer]).await;
+
+        assert_eq!(get_token_balance(open.initializer_x_account, &mut pt).await, 0);
+        assert_eq!(get_token_balance(open.temp_x_account, &mut pt).await, 150);
+        let escrow_info = pt.get_escrow(open.escrow_account).await;
+        assert_eq!(escrow_info.remaining_amount, 150);
+        assert_eq!(escrow_info.expected_amount, 1500);
+
+        // the taker takes everything, including the deposit, for the raised price
+        pt.process_tx_and_assert_ok(&[open.exchange_instruction(&pt.program_id, 150)], &[&open.taker]).await;
+        assert_eq!(get_token_balance(open.taker_x_account, &mut pt).await, 150);
+        assert_eq!(get_token_balance(open.initializer_y_account, &mut pt).await, 1500);
+
+        // the escrow is closed once taken, nothing more can be deposited into it
+        mint_some(&open.initializer_x_account, &open.mint_x.pubkey(), &mut pt, 60).await;
+        pt.process_tx_and_assert_err(&[
+            deposit_instruction(
+                &pt.program_id,
+                &open.initializer.pubkey(),
+                &open.initializer_x_account,
+                &open.temp_x_account,
+                &open.escrow_account,
+                60,
+                600,
+            )
+        ], &[&open.initializer], TransactionError::InstructionError(0, InstructionError::IncorrectProgramId)).await;
+    }
+
+    #[tokio::test]
+    async fn test_deposit_requires_initializer() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        let impostor = Keypair::new();
+        let impostor_x_account = initialize_token_account(&impostor.pubkey(), &open.mint_x.pubkey(), &mut pt).await;
+        mint_some(&impostor_x_account, &open.mint_x.pubkey(), &mut pt, 1).await;
+
+        // only the initializer may change the terms of the escrow
+        pt.process_tx_and_assert_err(&[
+            deposit_instruction(
+                &pt.program_id,
+                &impostor.pubkey(),
+                &impostor_x_account,
+                &open.temp_x_account,
+                &open.escrow_account,
+                1,
+                0,
+            )
+        ], &[&impostor], TransactionError::InstructionError(0, InstructionError::InvalidAccountData)).await;
+        assert_eq!(get_token_balance(open.temp_x_account, &mut pt).await, 100);
+    }
+
+    #[tokio::test]
+    async fn test_exchange_rejects_escrow_owned_by_another_program() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        // the same escrow state, but handed over to some other program
+        let escrow = pt.get_escrow(open.escrow_account).await;
+        let mut account = AccountSharedData::new(pt.rent.minimum_balance(Escrow::LEN), Escrow::LEN, &Pubkey::new_unique());
+        escrow.pack_into_slice(account.data_as_mut_slice());
+        pt.context.set_account(&open.escrow_account, &account);
+
+        let (result, logs) = pt.process_tx_capturing_logs(&[open.exchange_instruction(&pt.program_id, 100)], &[&open.taker]).await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            TransactionError::InstructionError(0, InstructionError::IncorrectProgramId),
+        );
+        let expected = format!("Program log: Escrow account {} is not owned by this program", open.escrow_account);
+        assert!(logs.contains(&expected), "{:?}", logs);
+        assert_eq!(get_token_balance(open.taker_y_account, &mut pt).await, 1000);
+        assert_eq!(get_token_balance(open.temp_x_account, &mut pt).await, 100);
+    }
+
+    #[tokio::test]
+    async fn test_escrow_owned_by_another_program_is_rejected_by_every_instruction() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        let escrow = pt.get_escrow(open.escrow_account).await;
+        let mut account = AccountSharedData::new(pt.rent.minimum_balance(Escrow::LEN), Escrow::LEN, &Pubkey::new_unique());
+        escrow.pack_into_slice(account.data_as_mut_slice());
+        pt.context.set_account(&open.escrow_account, &account);
+
+        let instructions = [
+            open.cancel_escrow_instruction(&pt.program_id),
+            deposit_instruction(
+                &pt.program_id,
+                &open.initializer.pubkey(),
+                &open.initializer_x_account,
+                &open.temp_x_account,
+                &open.escrow_account,
+                10,
+                100,
+            ),
+            transfer_ownership_instruction(
+                &pt.program_id,
+                &open.initializer.pubkey(),
+                &open.escrow_account,
+                &open.taker.pubkey(),
+                &open.taker_y_account,
+                &open.temp_x_account,
+                &open.pda,
+            ),
+        ];
+        for instruction in instructions {
+            pt.process_tx_and_assert_err(
+                &[instruction],
+                &[&open.initializer],
+                TransactionError::InstructionError(0, InstructionError::IncorrectProgramId),
+            ).await;
+        }
+        pt.process_tx_and_assert_err(
+            &[describe_escrow_instruction(&pt.program_id, &open.escrow_account)],
+            &[],
+            TransactionError::InstructionError(0, InstructionError::IncorrectProgramId),
+        ).await;
+
+        assert_eq!(get_token_balance(open.temp_x_account, &mut pt).await, 100);
+    }
+
+    #[tokio::test]
+    async fn test_init_escrow_require_self_receive() {
+        let mut pt = EscrowProgramTest::start_new().await;
+
+        let initializer = Keypair::new();
+        let someone_else = Keypair::new();
+        let mint_x = Keypair::new();
+        let mint_y = Keypair::new();
+        initialize_mint(&mint_x, 0, &mut pt).await;
+        initialize_mint(&mint_y, 0, &mut pt).await;
+
+        let initializer_y_account = initialize_token_account(&initializer.pubkey(), &mint_y.pubkey(), &mut pt).await;
+        let someone_elses_y_account = initialize_token_account(&someone_else.pubkey(), &mint_y.pubkey(), &mut pt).await;
+
+        let init = |pt: &EscrowProgramTest, temp_x_account: &Pubkey, token_to_receive_account: &Pubkey, escrow_account: &Pubkey, require_self_receive: bool| {
+            let mut init_ix = init_escrow_instruction(
+                &pt.program_id,
+                &initializer.pubkey(),
+                temp_x_account,
+                token_to_receive_account,
+                escrow_account,
+                1000,
+                i64::MAX,
+                &[],
+            );
+            init_ix.data = EscrowInstruction::InitEscrow {
+                amount: 1000,
+                unlock_timestamp: i64::MAX,
+                seed: DEFAULT_SEED.to_vec(),
+                memo: [0; MEMO_LEN],
+                require_self_receive,
+            }.pack();
+            init_ix
+        };
+
+        // with the flag set, a receive account belonging to someone else is rejected
+        let temp_x_account = initialize_token_account(&initializer.pubkey(), &mint_x.pubkey(), &mut pt).await;
+        mint_some(&temp_x_account, &mint_x.pubkey(), &mut pt, 100).await;
+        let escrow_account = pt.create_escrow_account().await;
+        let init_ix = init(&pt, &temp_x_account, &someone_elses_y_account, &escrow_account.pubkey(), true);
+        pt.process_tx_and_assert_err(
+            &[init_ix],
+            &[&initializer],
+            TransactionError::InstructionError(0, InstructionError::InvalidAccountData),
+        ).await;
+
+        // the initializer's own account passes the check
+        let init_ix = init(&pt, &temp_x_account, &initializer_y_account, &escrow_account.pubkey(), true);
+        pt.process_tx_and_assert_ok(&[init_ix], &[&initializer]).await;
+        let escrow_info = pt.get_escrow(escrow_account.pubkey()).await;
+        assert_eq!(escrow_info.initializer_token_to_receive_account_pubkey, initializer_y_account);
+
+        // without the flag, paying out to someone else is still allowed
+        let temp_x_account = initialize_token_account(&initializer.pubkey(), &mint_x.pubkey(), &mut pt).await;
+        mint_some(&temp_x_account, &mint_x.pubkey(), &mut pt, 50).await;
+        let escrow_account = pt.create_escrow_account().await;
+        let init_ix = init(&pt, &temp_x_account, &someone_elses_y_account, &escrow_account.pubkey(), false);
+        pt.process_tx_and_assert_ok(&[init_ix], &[&initializer]).await;
+        let escrow_info = pt.get_escrow(escrow_account.pubkey()).await;
+        assert_eq!(escrow_info.initializer_token_to_receive_account_pubkey, someone_elses_y_account);
+    }
+
+    #[tokio::test]
+    async fn test_describe_escrow_logs_its_fields() {
+        let mut pt = EscrowProgramTest::start_new().await;
+
+        let initializer = Keypair::new();
+        let mint_x = Keypair::new();
+        let mint_y = Keypair::new();
+        initialize_mint(&mint_x, 0, &mut pt).await;
+        initialize_mint(&mint_y, 0, &mut pt).await;
+
+        let initializer_y_account = initialize_token_account(&initializer.pubkey(), &mint_y.pubkey(), &mut pt).await;
+        let temp_x_account = initialize_token_account(&initializer.pubkey(), &mint_x.pubkey(), &mut pt).await;
+        mint_some(&temp_x_account, &mint_x.pubkey(), &mut pt, 100).await;
+
+        // describing an escrow nobody has initialized yet fails instead of logging zeros
+        let escrow_account = pt.create_escrow_account().await;
+        pt.process_tx_and_assert_err(
+            &[describe_escrow_instruction(&pt.program_id, &escrow_account.pubkey())],
+            &[],
+            TransactionError::InstructionError(0, InstructionError::UninitializedAccount),
+        ).await;
+
+        pt.process_tx_and_assert_ok(&[
+            init_escrow_instruction(
+                &pt.program_id,
+                &initializer.pubkey(),
+                &temp_x_account,
+                &initializer_y_account,
+                &escrow_account.pubkey(),
+                1000,
+                i64::MAX,
+                &[],
+            )
+        ], &[&initializer]).await;
+
+        // the rejected describe above was this very transaction, it needs a fresh blockhash to be sent again
+        pt.context.last_blockhash = pt.context.banks_client
+            .get_new_latest_blockhash(&pt.context.last_blockhash)
+            .await
+            .unwrap();
+        let escrow_before = pt.get_escrow(escrow_account.pubkey()).await;
+        let logs = pt.process_tx_and_return_logs(
+            &[describe_escrow_instruction(&pt.program_id, &escrow_account.pubkey())],
+            &[],
+        ).await;
+        let expected = format!(
+            "Program log: Escrow {{ initialized: true, initializer: {}, temp_token_account: {}, token_to_receive_account: {}, expected_amount: 1000 }}",
+            initializer.pubkey(),
+            temp_x_account,
+            initializer_y_account,
+        );
+        assert!(logs.contains(&expected), "{:?}", logs);
+        assert_eq!(pt.get_escrow(escrow_account.pubkey()).await, escrow_before);
+    }
+
+    #[tokio::test]
+    async fn test_same_seed_from_different_initializers_gets_different_pdas() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let first = open_escrow(&mut pt).await;
+        let second = open_escrow(&mut pt).await;
+
+        // both used DEFAULT_SEED, yet each temp account is held by its own initializer's PDA
+        assert_ne!(first.pda, second.pda);
+        for open in [&first, &second] {
+            let temp_account = pt.context.banks_client.get_account(open.temp_x_account).await.unwrap().unwrap();
+            assert_eq!(spl_token::state::Account::unpack(&temp_account.data).unwrap().owner, open.pda);
+        }
+
+        pt.process_tx_and_assert_ok(&[first.exchange_instruction(&pt.program_id, 100)], &[&first.taker]).await;
+        pt.process_tx_and_assert_ok(&[second.exchange_instruction(&pt.program_id, 100)], &[&second.taker]).await;
+        assert_eq!(get_token_balance(first.initializer_y_account, &mut pt).await, 1000);
+        assert_eq!(get_token_balance(second.initializer_y_account, &mut pt).await, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_init_escrow_stores_pda_bump() {
+        let mut pt = EscrowProgramTest::start_new().await;
+        let open = open_escrow(&mut pt).await;
+
+        let (pda, bump) = Pubkey::find_program_address(
+            &[b"escrow", open.initializer.pubkey().as_ref(), DEFAULT_SEED],
+            &pt.program_id,
+        );
+        let escrow_info = pt.get_escrow(open.escrow_account).await;
+        assert_eq!(escrow_info.bump, bump);
+        assert_eq!(escrow_info.pda(&pt.program_id), Ok(pda));
+        assert_eq!(open.pda, pda);
+
+        pt.process_tx_and_assert_ok(&[open.exchange_instruction(&pt.program_id, 100)], &[&open.taker]).await;
+        assert_eq!(get_token_balance(open.taker_x_account, &mut pt).await, 100);
+        assert_eq!(get_token_balance(open.initializer_y_account, &mut pt).await, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_missing_accounts_are_named_in_logs() {
+        let mut pt = EscrowProgramTest::start_new().await;
+
+        let initializer = Keypair::new();
+        let taker = Keypair::new();
+        let mint_x = Keypair::new();
+        let mint_y = Keypair::new();
+        initialize_mint(&mint_x, 0, &mut pt).await;
+        initialize_mint(&mint_y, 0, &mut pt).await;
+
+        let initializer_y_account = initialize_token_account(&initializer.pubkey(), &mint_y.pubkey(), &mut pt).await;
+        let temp_x_account = initialize_token_account(&initializer.pubkey(), &mint_x.pubkey(), &mut pt).await;
+        mint_some(&temp_x_account, &mint_x.pubkey(), &mut pt, 100).await;
+
+        let taker_x_account = initialize_token_account(&taker.pubkey(), &mint_x.pubkey(), &mut pt).await;
+        let taker_y_account = initialize_token_account(&taker.pubkey(), &mint_y.pubkey(), &mut pt).await;
+        mint_some(&taker_y_account, &mint_y.pubkey(), &mut pt, 1000).await;
+
+        let escrow_account = pt.create_escrow_account().await;
+        let mut init_ix = init_escrow_instruction(
+            &pt.program_id,
+            &initializer.pubkey(),
+            &temp_x_account,
+            &initializer_y_account,
+            &escrow_account.pubkey(),
+            1000,
+            i64::MAX,
+            &[],
+        );
+
+        // only the initializer
+        let mut truncated_ix = init_ix.clone();
+        truncated_ix.accounts.truncate(1);
+        let (result, logs) = pt.process_tx_capturing_logs(&[truncated_ix], &[&initializer]).await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys),
+        );
+        assert!(logs.contains(&"Program log: Missing account: temp_token_account".to_string()), "{:?}", logs);
+
+        init_ix.accounts.truncate(5);
+        let (result, logs) = pt.process_tx_capturing_logs(&[init_ix.clone()], &[&initializer]).await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys),
+        );
+        assert!(logs.contains(&"Program log: Missing account: token_program".to_string()), "{:?}", logs);
+
+        init_ix.accounts.push(AccountMeta::new_readonly(spl_token::id(), false));
+        pt.process_tx_and_assert_ok(&[init_ix], &[&initializer]).await;
+
+        // everything up to the clock, without the two mints
+        let pda = pt.pda(&initializer.pubkey());
+        let mut exchange_ix = exchange_instruction(
+            &pt.program_id,
+            &taker.pubkey(),
+            &taker_y_account,
+            &taker_x_account,
+            &temp_x_account,
+            &initializer.pubkey(),
+            &initializer_y_account,
+            &escrow_account.pubkey(),
+            &pda,
+            &mint_y.pubkey(),
+            &mint_x.pubkey(),
+            100,
+        );
+        exchange_ix.accounts.truncate(10);
+        let (result, logs) = pt.process_tx_capturing_logs(&[exchange_ix], &[&taker]).await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys),
+        );
+        assert!(logs.contains(&"Program log: Missing account: sending_mint_account".to_string()), "{:?}", logs);
+        assert_eq!(get_token_balance(temp_x_account, &mut pt).await, 100);
+    }
+}
@@ -0,0 +1,726 @@
+use assert_matches::assert_matches;
+use solana_escrow::{
+    processor::Processor,
+    state::{find_escrow_pda, Escrow, MAX_SEED_LEN},
+};
+use solana_program::{clock::Clock, program_pack::Pack, rent::Rent};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::{AccountSharedData, WritableAccount},
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction, sysvar,
+    transaction::{Transaction, TransactionError},
+    transaction_context::TransactionReturnData,
+};
+
+// seed of the escrows opened through init_escrow_instruction
+pub const DEFAULT_SEED: &[u8] = b"default";
+
+// what a simulated transaction did, without any of it being committed
+pub struct SimulationResult {
+    pub result: Option<Result<(), TransactionError>>,
+    pub logs: Vec<String>,
+    pub return_data: Option<TransactionReturnData>,
+    pub units_consumed: u64,
+}
+
+pub trait AddPacked {
+    fn add_packable_account<T: Pack>(
+        &mut self,
+        pubkey: Pubkey,
+        amount: u64,
+        data: &T,
+        owner: &Pubkey,
+    );
+}
+
+impl AddPacked for ProgramTest {
+    fn add_packable_account<T: Pack>(
+        &mut self,
+        pubkey: Pubkey,
+        amount: u64,
+        data: &T,
+        owner: &Pubkey,
+    ) {
+        let mut account = solana_sdk::account::Account::new(amount, T::get_packed_len(), owner);
+        data.pack_into_slice(&mut account.data);
+        self.add_account(pubkey, account);
+    }
+}
+
+pub struct EscrowProgramTest {
+    pub context: ProgramTestContext,
+    pub rent: Rent,
+    pub program_id: Pubkey,
+}
+
+impl EscrowProgramTest {
+    pub async fn start_new() -> Self {
+        Self::start_new_with(|_, _| {}).await
+    }
+
+    // lets a test preload accounts before the bank starts, e.g. packed token state through AddPacked
+    pub async fn start_new_with<F: FnOnce(&mut ProgramTest, &Pubkey)>(setup: F) -> Self {
+        let program_id = Pubkey::new_unique();
+        let mut pt = ProgramTest::new("solana_escrow", program_id, processor!(Processor::process));
+        setup(&mut pt, &program_id);
+        let mut context = pt.start_with_context().await;
+        let rent = context.banks_client.get_rent().await.unwrap();
+
+        Self {
+            context,
+            rent,
+            program_id,
+        }
+    }
+
+    pub async fn process_tx_and_assert_ok(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) {
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+
+        let tx = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            self.context.last_blockhash,
+        );
+
+        assert_matches!(
+            self.context.banks_client.process_transaction(tx).await,
+            Ok(())
+        );
+    }
+
+    // processes the transaction and returns its result along with the program logs, failed or not
+    pub async fn process_tx_capturing_logs(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> (Result<(), BanksClientError>, Vec<String>) {
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+
+        let tx = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            self.context.last_blockhash,
+        );
+
+        // the banks client only hands out logs for simulations, so the transaction is simulated
+        // first and only sent once the simulation went through
+        let simulation = self
+            .context
+            .banks_client
+            .simulate_transaction(tx.clone())
+            .await
+            .unwrap();
+        let logs = simulation
+            .simulation_details
+            .map(|details| details.logs)
+            .unwrap_or_default();
+        let result = match simulation.result {
+            Some(Ok(())) => self.context.banks_client.process_transaction(tx).await,
+            Some(Err(err)) => Err(BanksClientError::TransactionError(err)),
+            None => Err(BanksClientError::ClientError("simulation returned no result")),
+        };
+        (result, logs)
+    }
+
+    pub async fn process_tx_and_return_logs(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Vec<String> {
+        let (result, logs) = self.process_tx_capturing_logs(instructions, signers).await;
+        assert_matches!(result, Ok(()));
+        logs
+    }
+
+    pub async fn process_tx_and_assert_err(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+        transaction_error: TransactionError,
+    ) {
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+
+        let tx = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            self.context.last_blockhash,
+        );
+
+        assert_eq!(
+            transaction_error,
+            self.context
+                .banks_client
+                .process_transaction(tx)
+                .await
+                .unwrap_err()
+                .unwrap(),
+        );
+    }
+
+    pub async fn with_simulation<F: FnOnce(SimulationResult)>(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+        check: F,
+    ) {
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+
+        let tx = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            self.context.last_blockhash,
+        );
+
+        let simulation = self.context.banks_client.simulate_transaction(tx).await.unwrap();
+        let details = simulation.simulation_details.expect("simulation details");
+        check(SimulationResult {
+            result: simulation.result,
+            logs: details.logs,
+            return_data: details.return_data,
+            units_consumed: details.units_consumed,
+        });
+    }
+
+    // simulates the transaction, asserts it would succeed and returns the compute units it consumed
+    pub async fn measure_compute_units(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> u64 {
+        let mut units_consumed = 0;
+        self.with_simulation(instructions, signers, |simulation| {
+            assert_eq!(simulation.result, Some(Ok(())), "{:?}", simulation.logs);
+            units_consumed = simulation.units_consumed;
+        })
+        .await;
+        units_consumed
+    }
+
+    // the escrow account is created empty and owned by the program, InitEscrow fills it in
+    pub async fn create_escrow_account(&mut self) -> Keypair {
+        let escrow_keypair = Keypair::new();
+        let create_ix = system_instruction::create_account(
+            &self.context.payer.pubkey(),
+            &escrow_keypair.pubkey(),
+            self.rent.minimum_balance(Escrow::LEN),
+            Escrow::LEN as u64,
+            &self.program_id,
+        );
+        self.process_tx_and_assert_ok(&[create_ix], &[&escrow_keypair]).await;
+        escrow_keypair
+    }
+
+    // writes the escrow state directly, bypassing InitEscrow's checks
+    pub fn write_escrow(&mut self, escrow_account: &Pubkey, escrow: &Escrow) {
+        let mut account = AccountSharedData::new(
+            self.rent.minimum_balance(Escrow::LEN),
+            Escrow::LEN,
+            &self.program_id,
+        );
+        escrow.pack_into_slice(account.data_as_mut_slice());
+        self.context.set_account(escrow_account, &account);
+    }
+
+    pub async fn get_escrow(&mut self, pubkey: Pubkey) -> Escrow {
+        let account = self
+            .context
+            .banks_client
+            .get_account(pubkey)
+            .await
+            .unwrap()
+            .expect("escrow account not found");
+        Escrow::unpack_unchecked(&account.data).unwrap()
+    }
+
+    // byte exact comparison, locks in the serialized layout of an account
+    pub async fn assert_account_data_eq(&mut self, pubkey: Pubkey, expected: &[u8]) {
+        let account = self
+            .context
+            .banks_client
+            .get_account(pubkey)
+            .await
+            .unwrap()
+            .unwrap_or_else(|| panic!("account {} does not exist", pubkey));
+        assert_eq!(account.data, expected, "unexpected data in account {}", pubkey);
+    }
+
+    pub async fn get_clock(&mut self) -> Clock {
+        self.context.banks_client.get_sysvar::<Clock>().await.unwrap()
+    }
+
+    pub async fn set_unix_timestamp(&mut self, unix_timestamp: i64) {
+        let mut clock = self.get_clock().await;
+        clock.unix_timestamp = unix_timestamp;
+        self.context.set_sysvar(&clock);
+    }
+
+    // opens `count` escrows offering `amount` of `mint` each, every one with its own funded temp account
+    pub async fn init_many_escrows(
+        &mut self,
+        count: usize,
+        initializer: &Keypair,
+        mint: &Pubkey,
+        token_to_receive_account: &Pubkey,
+        amount: u64,
+    ) -> Vec<Pubkey> {
+        let mut escrow_accounts = Vec::with_capacity(count);
+        for _ in 0..count {
+            let temp_token_account = initialize_token_account(&initializer.pubkey(), mint, self).await;
+            mint_some(&temp_token_account, mint, self, amount).await;
+            let escrow_account = self.create_escrow_account().await;
+            self.process_tx_and_assert_ok(
+                &[init_escrow_instruction(
+                    &self.program_id,
+                    &initializer.pubkey(),
+                    &temp_token_account,
+                    token_to_receive_account,
+                    &escrow_account.pubkey(),
+                    amount,
+                    i64::MAX,
+                    &[],
+                )],
+                &[initializer],
+            )
+            .await;
+            escrow_accounts.push(escrow_account.pubkey());
+        }
+        escrow_accounts
+    }
+
+    // the PDA of the initializer's escrows opened with DEFAULT_SEED
+    pub fn pda(&self, initializer: &Pubkey) -> Pubkey {
+        self.pda_for_seed(initializer, DEFAULT_SEED)
+    }
+
+    pub fn pda_for_seed(&self, initializer: &Pubkey, seed: &[u8]) -> Pubkey {
+        find_escrow_pda(&self.program_id, initializer, seed).0
+    }
+}
+
+// an escrow offering 100 X for 1000 Y, with a taker holding the 1000 Y to take it
+pub struct OpenEscrow {
+    pub initializer: Keypair,
+    pub taker: Keypair,
+    pub mint_x: Keypair,
+    pub mint_y: Keypair,
+    // empty, where cancelled or reclaimed X tokens go back to
+    pub initializer_x_account: Pubkey,
+    pub initializer_y_account: Pubkey,
+    pub temp_x_account: Pubkey,
+    pub taker_x_account: Pubkey,
+    pub taker_y_account: Pubkey,
+    pub escrow_account: Pubkey,
+    pub pda: Pubkey,
+}
+
+impl OpenEscrow {
+    pub fn exchange_instruction(&self, program_id: &Pubkey, amount: u64) -> Instruction {
+        exchange_instruction(
+            program_id,
+            &self.taker.pubkey(),
+            &self.taker_y_account,
+            &self.taker_x_account,
+            &self.temp_x_account,
+            &self.initializer.pubkey(),
+            &self.initializer_y_account,
+            &self.escrow_account,
+            &self.pda,
+            &self.mint_y.pubkey(),
+            &self.mint_x.pubkey(),
+            amount,
+        )
+    }
+
+    pub fn cancel_escrow_instruction(&self, program_id: &Pubkey) -> Instruction {
+        cancel_escrow_instruction(
+            program_id,
+            &self.initializer.pubkey(),
+            &self.temp_x_account,
+            &self.initializer_x_account,
+            &self.escrow_account,
+            &self.pda,
+        )
+    }
+}
+
+pub async fn open_escrow(pt: &mut EscrowProgramTest) -> OpenEscrow {
+    open_escrow_with(pt, i64::MAX, &[]).await
+}
+
+pub async fn open_escrow_with(
+    pt: &mut EscrowProgramTest,
+    unlock_timestamp: i64,
+    memo: &[u8],
+) -> OpenEscrow {
+    let initializer = Keypair::new();
+    let taker = Keypair::new();
+    let mint_x = Keypair::new();
+    let mint_y = Keypair::new();
+    initialize_mint(&mint_x, 0, pt).await;
+    initialize_mint(&mint_y, 0, pt).await;
+
+    let initializer_x_account = initialize_token_account(&initializer.pubkey(), &mint_x.pubkey(), pt).await;
+    let initializer_y_account = initialize_token_account(&initializer.pubkey(), &mint_y.pubkey(), pt).await;
+    let temp_x_account = initialize_token_account(&initializer.pubkey(), &mint_x.pubkey(), pt).await;
+    mint_some(&temp_x_account, &mint_x.pubkey(), pt, 100).await;
+
+    let taker_x_account = initialize_token_account(&taker.pubkey(), &mint_x.pubkey(), pt).await;
+    let taker_y_account = initialize_token_account(&taker.pubkey(), &mint_y.pubkey(), pt).await;
+    mint_some(&taker_y_account, &mint_y.pubkey(), pt, 1000).await;
+
+    let escrow_account = pt.create_escrow_account().await.pubkey();
+    pt.process_tx_and_assert_ok(
+        &[init_escrow_instruction(
+            &pt.program_id,
+            &initializer.pubkey(),
+            &temp_x_account,
+            &initializer_y_account,
+            &escrow_account,
+            1000,
+            unlock_timestamp,
+            memo,
+        )],
+        &[&initializer],
+    )
+    .await;
+
+    OpenEscrow {
+        pda: pt.pda(&initializer.pubkey()),
+        initializer,
+        taker,
+        mint_x,
+        mint_y,
+        initializer_x_account,
+        initializer_y_account,
+        temp_x_account,
+        taker_x_account,
+        taker_y_account,
+        escrow_account,
+    }
+}
+
+// catches accounts accidentally added to or dropped from an instruction builder
+pub fn assert_account_count(metas: &[AccountMeta], expected: usize) {
+    assert_eq!(
+        metas.len(),
+        expected,
+        "expected {} accounts but the instruction has {}",
+        expected,
+        metas.len()
+    );
+}
+
+// the seed as stored in the escrow state, zero padded to MAX_SEED_LEN
+pub fn padded_seed(seed: &[u8]) -> [u8; MAX_SEED_LEN] {
+    let mut padded = [0; MAX_SEED_LEN];
+    padded[..seed.len()].copy_from_slice(seed);
+    padded
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn init_escrow_instruction(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    temp_token_account: &Pubkey,
+    token_to_receive_account: &Pubkey,
+    escrow_account: &Pubkey,
+    amount: u64,
+    unlock_timestamp: i64,
+    memo: &[u8],
+) -> Instruction {
+    init_escrow_instruction_with_seed(
+        program_id,
+        initializer,
+        temp_token_account,
+        token_to_receive_account,
+        escrow_account,
+        amount,
+        unlock_timestamp,
+        DEFAULT_SEED,
+        memo,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn init_escrow_instruction_with_seed(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    temp_token_account: &Pubkey,
+    token_to_receive_account: &Pubkey,
+    escrow_account: &Pubkey,
+    amount: u64,
+    unlock_timestamp: i64,
+    seed: &[u8],
+    memo: &[u8],
+) -> Instruction {
+    let mut data = vec![0];
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.extend_from_slice(&unlock_timestamp.to_le_bytes());
+    data.push(seed.len() as u8);
+    data.extend_from_slice(seed);
+    data.extend_from_slice(memo);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*initializer, true),
+            AccountMeta::new(*temp_token_account, false),
+            AccountMeta::new_readonly(*token_to_receive_account, false),
+            AccountMeta::new(*escrow_account, false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn exchange_instruction(
+    program_id: &Pubkey,
+    taker: &Pubkey,
+    takers_sending_token_account: &Pubkey,
+    takers_token_to_receive_account: &Pubkey,
+    temp_token_account: &Pubkey,
+    initializer: &Pubkey,
+    initializers_token_to_receive_account: &Pubkey,
+    escrow_account: &Pubkey,
+    pda: &Pubkey,
+    sending_mint: &Pubkey,
+    receiving_mint: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let mut data = vec![1];
+    data.extend_from_slice(&amount.to_le_bytes());
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*taker, true),
+            AccountMeta::new(*takers_sending_token_account, false),
+            AccountMeta::new(*takers_token_to_receive_account, false),
+            AccountMeta::new(*temp_token_account, false),
+            AccountMeta::new(*initializer, false),
+            AccountMeta::new(*initializers_token_to_receive_account, false),
+            AccountMeta::new(*escrow_account, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(*pda, false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(*sending_mint, false),
+            AccountMeta::new_readonly(*receiving_mint, false),
+        ],
+        data,
+    }
+}
+
+pub fn cancel_escrow_instruction(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    temp_token_account: &Pubkey,
+    initializer_token_account: &Pubkey,
+    escrow_account: &Pubkey,
+    pda: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*initializer, true),
+            AccountMeta::new(*temp_token_account, false),
+            AccountMeta::new(*initializer_token_account, false),
+            AccountMeta::new(*escrow_account, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(*pda, false),
+        ],
+        data: vec![2],
+    }
+}
+
+// takes the same accounts as CancelEscrow
+pub fn reclaim_dust_instruction(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    temp_token_account: &Pubkey,
+    initializer_token_account: &Pubkey,
+    escrow_account: &Pubkey,
+    pda: &Pubkey,
+) -> Instruction {
+    let mut instruction = cancel_escrow_instruction(
+        program_id,
+        initializer,
+        temp_token_account,
+        initializer_token_account,
+        escrow_account,
+        pda,
+    );
+    instruction.data = vec![5];
+    instruction
+}
+
+pub fn deposit_instruction(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    initializer_token_account: &Pubkey,
+    temp_token_account: &Pubkey,
+    escrow_account: &Pubkey,
+    amount: u64,
+    expected_amount_delta: u64,
+) -> Instruction {
+    let mut data = vec![6];
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.extend_from_slice(&expected_amount_delta.to_le_bytes());
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*initializer, true),
+            AccountMeta::new(*initializer_token_account, false),
+            AccountMeta::new(*temp_token_account, false),
+            AccountMeta::new(*escrow_account, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data,
+    }
+}
+
+pub fn transfer_ownership_instruction(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    escrow_account: &Pubkey,
+    new_initializer: &Pubkey,
+    new_token_to_receive_account: &Pubkey,
+    temp_token_account: &Pubkey,
+    pda: &Pubkey,
+) -> Instruction {
+    let mut data = vec![4];
+    data.extend_from_slice(new_initializer.as_ref());
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*initializer, true),
+            AccountMeta::new(*escrow_account, false),
+            AccountMeta::new_readonly(*new_token_to_receive_account, false),
+            AccountMeta::new(*temp_token_account, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(*pda, false),
+        ],
+        data,
+    }
+}
+
+pub fn describe_escrow_instruction(program_id: &Pubkey, escrow_account: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![AccountMeta::new_readonly(*escrow_account, false)],
+        data: vec![7],
+    }
+}
+
+// To simplify, the payer is mint authority of all mints
+pub async fn initialize_mint(
+    mint_keypair: &Keypair,
+    decimals: u8,
+    escrow_program_test: &mut EscrowProgramTest,
+) {
+    let mint_rent_exempt_threshold = escrow_program_test
+        .rent
+        .minimum_balance(spl_token::state::Mint::LEN);
+
+    escrow_program_test
+        .process_tx_and_assert_ok(
+            &[
+                system_instruction::create_account(
+                    &escrow_program_test.context.payer.pubkey(),
+                    &mint_keypair.pubkey(),
+                    mint_rent_exempt_threshold,
+                    spl_token::state::Mint::LEN as u64,
+                    &spl_token::id(),
+                ),
+                spl_token::instruction::initialize_mint(
+                    &spl_token::id(),
+                    &mint_keypair.pubkey(),
+                    &escrow_program_test.context.payer.pubkey(),
+                    None,
+                    decimals,
+                )
+                .unwrap(),
+            ],
+            &[mint_keypair],
+        )
+        .await;
+}
+
+// plain (non associated) token account, like the temp account the initializer hands over to the PDA
+pub async fn initialize_token_account(
+    owner: &Pubkey,
+    mint: &Pubkey,
+    escrow_program_test: &mut EscrowProgramTest,
+) -> Pubkey {
+    let account_keypair = Keypair::new();
+    let account_rent_exempt_threshold = escrow_program_test
+        .rent
+        .minimum_balance(spl_token::state::Account::LEN);
+
+    escrow_program_test
+        .process_tx_and_assert_ok(
+            &[
+                system_instruction::create_account(
+                    &escrow_program_test.context.payer.pubkey(),
+                    &account_keypair.pubkey(),
+                    account_rent_exempt_threshold,
+                    spl_token::state::Account::LEN as u64,
+                    &spl_token::id(),
+                ),
+                spl_token::instruction::initialize_account(
+                    &spl_token::id(),
+                    &account_keypair.pubkey(),
+                    mint,
+                    owner,
+                )
+                .unwrap(),
+            ],
+            &[&account_keypair],
+        )
+        .await;
+    account_keypair.pubkey()
+}
+
+pub async fn mint_some(
+    token_account: &Pubkey,
+    mint: &Pubkey,
+    escrow_program_test: &mut EscrowProgramTest,
+    amount: u64,
+) {
+    escrow_program_test
+        .process_tx_and_assert_ok(
+            &[spl_token::instruction::mint_to(
+                &spl_token::id(),
+                mint,
+                token_account,
+                &escrow_program_test.context.payer.pubkey(),
+                &[],
+                amount,
+            )
+            .unwrap()],
+            &[],
+        )
+        .await;
+}
+
+pub async fn get_token_balance(pubkey: Pubkey, escrow_program_test: &mut EscrowProgramTest) -> u64 {
+    let token = escrow_program_test.context.banks_client.get_account(pubkey).await.unwrap().unwrap();
+
+    spl_token::state::Account::unpack(&token.data[..])
+        .unwrap()
+        .amount
+}
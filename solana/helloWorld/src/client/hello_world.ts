@@ -20,7 +20,7 @@ import {getPayer, getRpcUrl, createKeypairFromFile} from './utils';
 // @ts-ignore
 import * as BufferLayout from "buffer-layout";
 import {struct, u32} from "@solana/buffer-layout";
-import {u64} from "@solana/buffer-layout-utils";
+import {i64, u64} from "@solana/buffer-layout-utils";
 /**
  * Connection to the network
  */
@@ -66,20 +66,32 @@ const PROGRAM_KEYPAIR_PATH = path.join(PROGRAM_PATH, 'helloworld-keypair.json');
 class GreetingAccount {
   counter = 0;
   free_counter: bigint = BigInt(0);
-  constructor(fields: {counter: number, free_counter: bigint} | undefined = undefined) {
+  last_greeted_ts: bigint = BigInt(0);
+  current_streak = 0;
+  best_streak = 0;
+  constructor(fields: {counter: number, free_counter: bigint, last_greeted_ts: bigint, current_streak: number, best_streak: number} | undefined = undefined) {
     if (fields) {
       this.counter = fields.counter;
       this.free_counter = fields.free_counter;
+      this.last_greeted_ts = fields.last_greeted_ts;
+      this.current_streak = fields.current_streak;
+      this.best_streak = fields.best_streak;
     }
   }
 }
 export interface GreetingAccountInterface {
   counter: number
   free_counter: bigint
+  last_greeted_ts: bigint
+  current_streak: number
+  best_streak: number
 }
 export const GreetingAccountLayout = struct<GreetingAccountInterface>([
     u32('counter'),
-    u64('free_counter')
+    u64('free_counter'),
+    i64('last_greeted_ts'),
+    u32('current_streak'),
+    u32('best_streak')
 ])
 
 /**
@@ -89,7 +101,8 @@ const GreetingSchema = new Map([
   [GreetingAccount, {
     kind: 'struct',
     fields:
-        [['counter', 'u32'], ['free_counter', 'u64']]
+        // borsh 0.6 has no signed integers, timestamps are positive so u64 reads the same bytes
+        [['counter', 'u32'], ['free_counter', 'u64'], ['last_greeted_ts', 'u64'], ['current_streak', 'u32'], ['best_streak', 'u32']]
   }
   ],
 ]);
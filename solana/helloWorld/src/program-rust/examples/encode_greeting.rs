@@ -0,0 +1,20 @@
+//! Prints the instruction data a client sends to greet an account, as hex
+//!
+//! `$ cargo run --example encode_greeting -- <amount>`
+
+use helloworld::GreetingInstruction;
+use std::{env, process};
+
+fn main() {
+    let amount = match env::args().nth(1).map(|arg| arg.parse::<u64>()) {
+        Some(Ok(amount)) => amount,
+        _ => {
+            eprintln!("usage: encode_greeting <amount>");
+            process::exit(1);
+        }
+    };
+
+    let data = GreetingInstruction::InitGreeting { amount }.pack();
+    let hex: String = data.iter().map(|byte| format!("{:02x}", byte)).collect();
+    println!("{}", hex);
+}
@@ -5,16 +5,31 @@ use solana_program::{
     entrypoint,
     entrypoint::ProgramResult,
     msg,
+    program::{invoke, invoke_signed, set_return_data},
     program_error::ProgramError,
     program_pack::{Pack, Sealed},
     pubkey::Pubkey,
+    system_instruction, system_program,
+    sysvar::{self, clock::Clock, rent::Rent, Sysvar},
 };
-use std::convert::{TryInto};
+use std::convert::{TryFrom, TryInto};
 
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 use std::mem;
 
-#[derive(Error, Debug, Copy, Clone)]
+/// Version of this program, returned by the `Version` instruction
+pub const VERSION: u32 = 1;
+
+/// Seed of the PDA counting how many greetings a referrer brought in
+pub const REFERRER_SEED: &[u8] = b"referrer";
+
+/// Greetings at most this many seconds apart extend the current streak
+pub const STREAK_WINDOW: i64 = 24 * 60 * 60;
+
+/// Minimum number of seconds between two greetings of the same account, by `InitGreeting` or `RecordGreeting`
+pub const GREETING_COOLDOWN: i64 = 60;
+
+#[derive(Error, Debug, Copy, Clone, PartialEq)]
 pub enum GreetingError {
     /// Invalid instruction
     #[error("Invalid Instruction")]
@@ -22,6 +37,18 @@ pub enum GreetingError {
     /// Not Rent Exempt
     #[error("Not Rent Exempt")]
     NotRentExempt,
+    /// Instruction data is too short to hold the amount
+    #[error("Missing Amount")]
+    MissingAmount,
+    /// A counter would overflow
+    #[error("Counter Overflow")]
+    CounterOverflow,
+    /// A counter holds less than what is taken from it
+    #[error("Counter Underflow")]
+    CounterUnderflow,
+    /// The account was greeted less than `GREETING_COOLDOWN` seconds ago
+    #[error("Cooldown Active")]
+    CooldownActive,
 }
 
 impl From<GreetingError> for ProgramError {
@@ -30,27 +57,80 @@ impl From<GreetingError> for ProgramError {
     }
 }
 
+/// Maps the code of a `ProgramError::Custom` back to its variant, for clients decoding errors
+impl TryFrom<u32> for GreetingError {
+    type Error = ();
+
+    fn try_from(code: u32) -> Result<Self, Self::Error> {
+        match code {
+            0 => Ok(GreetingError::InvalidInstruction),
+            1 => Ok(GreetingError::NotRentExempt),
+            2 => Ok(GreetingError::MissingAmount),
+            3 => Ok(GreetingError::CounterOverflow),
+            4 => Ok(GreetingError::CounterUnderflow),
+            5 => Ok(GreetingError::CooldownActive),
+            _ => Err(()),
+        }
+    }
+}
+
 /// Define the type of state stored in accounts
-#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Default)]
 pub struct GreetingAccount {
     /// number of greetings
     pub counter: u32,
     pub free_counter: u64,
+    /// unix timestamp of the latest greeting, zero before the first one, see `GREETING_COOLDOWN`
+    pub last_greeted_ts: i64,
+    /// greetings in a row, each within `STREAK_WINDOW` of the previous one
+    pub current_streak: u32,
+    pub best_streak: u32,
+}
+
+impl GreetingAccount {
+    /// Whether greeting at `now` is too soon after the previous greeting, the first greeting never is
+    pub fn cooldown_active(&self, now: i64) -> bool {
+        self.last_greeted_ts != 0 && now.saturating_sub(self.last_greeted_ts) < GREETING_COOLDOWN
+    }
+
+    /// Extends the streak when greeted within `STREAK_WINDOW` of the previous greeting, restarts it otherwise
+    pub fn record_streak(&mut self, now: i64) -> Result<(), GreetingError> {
+        let within_window = self.last_greeted_ts != 0
+            && now.saturating_sub(self.last_greeted_ts) <= STREAK_WINDOW;
+        self.current_streak = if within_window {
+            self.current_streak
+                .checked_add(1)
+                .ok_or(GreetingError::CounterOverflow)?
+        } else {
+            1
+        };
+        self.best_streak = self.best_streak.max(self.current_streak);
+        self.last_greeted_ts = now;
+        Ok(())
+    }
 }
 
 impl Sealed for GreetingAccount { }
 
 impl Pack for GreetingAccount {
-    const LEN: usize = 12;
+    /// Grew from 12 bytes when `last_greeted_ts` and the streaks were added. There is no migration,
+    /// accounts created at the old size fail to unpack and have to be recreated at this size
+    const LEN: usize = 28;
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
         let src = array_ref![src, 0, GreetingAccount::LEN];
         let (
             counter,
-            free_counter
-        ) = array_refs![src, 4, 8];
+            free_counter,
+            last_greeted_ts,
+            current_streak,
+            best_streak
+        ) = array_refs![src, 4, 8, 8, 4, 4];
         Ok(GreetingAccount {
             counter: u32::from_le_bytes(*counter),
-            free_counter: u64::from_le_bytes(*free_counter)
+            free_counter: u64::from_le_bytes(*free_counter),
+            last_greeted_ts: i64::from_le_bytes(*last_greeted_ts),
+            current_streak: u32::from_le_bytes(*current_streak),
+            best_streak: u32::from_le_bytes(*best_streak)
         })
     }
 
@@ -58,31 +138,133 @@ impl Pack for GreetingAccount {
         let dst = array_mut_ref![dst, 0, GreetingAccount::LEN];
         let (
             counter_dst,
-            free_counter_dst
-        ) = mut_array_refs![dst, 4, 8];
+            free_counter_dst,
+            last_greeted_ts_dst,
+            current_streak_dst,
+            best_streak_dst
+        ) = mut_array_refs![dst, 4, 8, 8, 4, 4];
 
         let GreetingAccount {
             counter,
             free_counter,
+            last_greeted_ts,
+            current_streak,
+            best_streak,
         } = self;
         *counter_dst = counter.to_le_bytes();
         *free_counter_dst = free_counter.to_le_bytes();
+        *last_greeted_ts_dst = last_greeted_ts.to_le_bytes();
+        *current_streak_dst = current_streak.to_le_bytes();
+        *best_streak_dst = best_streak.to_le_bytes();
     }
 }
+/// Aggregate over many greeted accounts, updated by `RecordGreeting`
+#[derive(PartialEq, Debug, Default)]
+pub struct GreetingStats {
+    pub total_greetings: u64,
+    /// greeted accounts recorded for the first time
+    pub unique_greeter_count: u32,
+}
+
+impl Sealed for GreetingStats { }
+
+impl Pack for GreetingStats {
+    const LEN: usize = 12;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, GreetingStats::LEN];
+        let (
+            total_greetings,
+            unique_greeter_count
+        ) = array_refs![src, 8, 4];
+        Ok(GreetingStats {
+            total_greetings: u64::from_le_bytes(*total_greetings),
+            unique_greeter_count: u32::from_le_bytes(*unique_greeter_count)
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, GreetingStats::LEN];
+        let (
+            total_greetings_dst,
+            unique_greeter_count_dst
+        ) = mut_array_refs![dst, 8, 4];
+
+        let GreetingStats {
+            total_greetings,
+            unique_greeter_count,
+        } = self;
+        *total_greetings_dst = total_greetings.to_le_bytes();
+        *unique_greeter_count_dst = unique_greeter_count.to_le_bytes();
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub enum GreetingInstruction {
     /// Accounts expected;
     ///
-    /// 0. `[signer]`   The account of the person initializing the escrow
-    /// 1. `[writable]` Temporary token account that should be created prior to this instruction and owned by the initializer
-    /// 2. `[]`         The initializer's token account for the token they will receive should the trade go throught
-    /// 3. `[writable]` The escrow account, it will hold all necessary info about the trade
-    /// 4. `[]`         The rent sysvar
-    /// 5. `[]`         The token program
+    /// 0. `[writable]` The greeted account, owned by this program
+    /// 1. `[]`         The greeter
+    /// 2. `[]`         (optional) The rent sysvar, checks the greeted account is rent exempt when supplied
+    /// 3. `[signer, writable]` (optional) The referrer, pays for their counter PDA the first time they refer someone
+    /// 4. `[writable]` (optional) The referrer's counter PDA, see `referrer_counter_address`, required with the referrer
+    /// 5. `[]`         (optional) The system program, required with the referrer
 
     InitGreeting {
         // The amount party A expects to receive of token Y
         amount: u64,
     },
+
+    /// Returns `VERSION` as little endian return data, no accounts expected
+    Version,
+
+    /// Greets an account and records the greeting in a shared `GreetingStats` account,
+    /// an account greeted for the first time also counts as a new unique greeter
+    ///
+    /// Accounts expected;
+    ///
+    /// 0. `[writable]` The greeted account, owned by this program
+    /// 1. `[writable]` The stats account, owned by this program
+    RecordGreeting,
+
+    /// Moves `per_account_amount` of the source's free counter to every destination
+    ///
+    /// Accounts expected;
+    ///
+    /// 0. `[writable, signer]` The source greeted account, owned by this program
+    /// 1. ..                   `[writable]` The destination greeted accounts, owned by this program
+    Distribute {
+        per_account_amount: u64,
+    },
+
+    /// Logs the stored counters without mutating the account, useful for simulated reads
+    ///
+    /// Accounts expected;
+    ///
+    /// 0. `[]` The greeted account, owned by this program
+    GetCounter,
+
+    /// Takes `amount` credits out of the free counter, failing with `CounterUnderflow` when there aren't enough
+    ///
+    /// Accounts expected;
+    ///
+    /// 0. `[writable, signer]` The greeted account, owned by this program
+    Spend {
+        amount: u64,
+    },
+
+    /// Creates the greeted account when it doesn't exist yet, then greets it like `InitGreeting`.
+    /// An account already owned by this program is greeted without being created again
+    ///
+    /// Accounts expected;
+    ///
+    /// 0. `[writable, signer]` The payer funding the new account, also the greeter
+    /// 1. `[writable, signer]` The greeted account, see above
+    /// 2. `[]`                 The system program
+    InitAndGreet {
+        amount: u64,
+        /// bytes allocated for the new account, following `amount`, must be `GreetingAccount::LEN`
+        space: u64,
+    },
 }
 
 impl GreetingInstruction {
@@ -94,11 +276,65 @@ impl GreetingInstruction {
             0 => Self::InitGreeting {
                 amount: Self::unpack_amount(rest)?,
             },
+            1 => Self::unpack_empty(rest, Self::Version)?,
+            2 => Self::Distribute {
+                per_account_amount: Self::unpack_amount(rest)?,
+            },
+            3 => Self::unpack_empty(rest, Self::RecordGreeting)?,
+            4 => Self::unpack_empty(rest, Self::GetCounter)?,
+            5 => Self::Spend {
+                amount: Self::unpack_amount(rest)?,
+            },
+            6 => {
+                if rest.len() < 16 {
+                    return Err(GreetingError::MissingAmount.into());
+                }
+                let (amount, space) = rest.split_at(8);
+                Self::InitAndGreet {
+                    amount: Self::unpack_amount(amount)?,
+                    space: Self::unpack_amount(space)?,
+                }
+            }
             _ => return Err(GreetingError::InvalidInstruction.into()),
         })
     }
 
+    /// Packs a [GreetingInstruction](enum.GreetingInstruction.html) into the byte buffer `unpack` reads
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(1 + 8 + 8);
+        match self {
+            Self::InitGreeting { amount } => {
+                buf.push(0);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::Version => buf.push(1),
+            Self::Distribute { per_account_amount } => {
+                buf.push(2);
+                buf.extend_from_slice(&per_account_amount.to_le_bytes());
+            }
+            Self::RecordGreeting => buf.push(3),
+            Self::GetCounter => buf.push(4),
+            Self::Spend { amount } => {
+                buf.push(5);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::InitAndGreet { amount, space } => {
+                buf.push(6);
+                buf.extend_from_slice(&amount.to_le_bytes());
+                buf.extend_from_slice(&space.to_le_bytes());
+            }
+        }
+        buf
+    }
+
+    // The payload must be exactly the amount, trailing bytes point at a client encoding bug
     fn unpack_amount(input: &[u8]) -> Result<u64, ProgramError> {
+        if input.len() < 8 {
+            return Err(GreetingError::MissingAmount.into());
+        }
+        if input.len() > 8 {
+            return Err(GreetingError::InvalidInstruction.into());
+        }
         let amount = input
             .get(..8)
             .and_then(|slice| slice.try_into().ok())
@@ -106,6 +342,14 @@ impl GreetingInstruction {
             .ok_or(GreetingError::InvalidInstruction)?;
         Ok(amount)
     }
+
+    // Tags without a payload must not be followed by any byte
+    fn unpack_empty(input: &[u8], instruction: Self) -> Result<Self, ProgramError> {
+        if !input.is_empty() {
+            return Err(GreetingError::InvalidInstruction.into());
+        }
+        Ok(instruction)
+    }
 }
 
 // Declare and export the program's entrypoint
@@ -134,6 +378,30 @@ impl Processor {
                 msg!("Instruction: InitGreeting");
                 Self::process_greeting(program_id,accounts, amount, instruction_data)
             }
+            GreetingInstruction::Version => {
+                msg!("Instruction: Version");
+                Self::process_version()
+            }
+            GreetingInstruction::Distribute { per_account_amount } => {
+                msg!("Instruction: Distribute");
+                Self::process_distribute(program_id, accounts, per_account_amount)
+            }
+            GreetingInstruction::RecordGreeting => {
+                msg!("Instruction: RecordGreeting");
+                Self::process_record_greeting(program_id, accounts)
+            }
+            GreetingInstruction::GetCounter => {
+                msg!("Instruction: GetCounter");
+                Self::process_get_counter(program_id, accounts)
+            }
+            GreetingInstruction::Spend { amount } => {
+                msg!("Instruction: Spend");
+                Self::process_spend(program_id, accounts, amount)
+            }
+            GreetingInstruction::InitAndGreet { amount, space } => {
+                msg!("Instruction: InitAndGreet");
+                Self::process_init_and_greet(program_id, accounts, amount, space)
+            }
         }
     }
     // Program entrypoint's implementation
@@ -156,18 +424,291 @@ impl Processor {
             return Err(ProgramError::IncorrectProgramId);
         }
 
+        // The rent sysvar is optional, only check rent exemption when it is supplied
+        let mut optional_accounts = accounts_iter.as_slice();
+        if let Some(rent_account) = optional_accounts.first().filter(|a| sysvar::rent::check_id(a.key)) {
+            let rent = &Rent::from_account_info(rent_account)?;
+            if !rent.is_exempt(account.lamports(), account.data_len()) {
+                return Err(GreetingError::NotRentExempt.into());
+            }
+            optional_accounts = &optional_accounts[1..];
+        }
+
+        // The referrer is optional too, when present its counter PDA is credited as well
+        let accounts_iter = &mut optional_accounts.iter();
+        if let Some(referrer) = accounts_iter.next() {
+            let referrer_counter = next_account_info(accounts_iter)?;
+            let system_program = next_account_info(accounts_iter)?;
+            Self::credit_referrer(program_id, referrer, referrer_counter, system_program)?;
+        }
+
        // Increment and store the number of times the account has been greeted
         let mut greeting_account = GreetingAccount::unpack_unchecked(&account.data.borrow())?;
-        greeting_account.counter += 1;
-        greeting_account.free_counter += amount;
+        let now = Clock::get()?.unix_timestamp;
+        if greeting_account.cooldown_active(now) {
+            msg!("Greeted too recently, last greeting at {}", greeting_account.last_greeted_ts);
+            return Err(GreetingError::CooldownActive.into());
+        }
+        greeting_account.counter = greeting_account
+            .counter
+            .checked_add(1)
+            .ok_or(GreetingError::CounterOverflow)?;
+        greeting_account.free_counter = greeting_account
+            .free_counter
+            .checked_add(amount)
+            .ok_or(GreetingError::CounterOverflow)?;
+        greeting_account.record_streak(now)?;
         greeting_account.serialize(&mut &mut account.data.borrow_mut()[..])?;
 
         msg!("Greeted {} time(s)!", greeting_account.counter);
         msg!("Free counter: {}", greeting_account.free_counter);
+        msg!("Streak: {} (best {})", greeting_account.current_streak, greeting_account.best_streak);
         msg!("Greeted from {}!", greeter.key);
+        emit_greeting_event(account.key, greeting_account.counter, greeting_account.free_counter);
+
+        Ok(())
+    }
+
+    pub fn process_init_and_greet(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        amount: u64,
+        space: u64,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let payer = next_account_info(accounts_iter)?;
+        let account = next_account_info(accounts_iter)?;
+        let system_program = next_account_info(accounts_iter)?;
+
+        // An account this program already owns was created before, it is only greeted
+        if account.owner != program_id {
+            if space != GreetingAccount::LEN as u64 {
+                msg!("Greeted account must be {} bytes, not {}", GreetingAccount::LEN, space);
+                return Err(GreetingError::InvalidInstruction.into());
+            }
+            let create_ix = system_instruction::create_account(
+                payer.key,
+                account.key,
+                Rent::get()?.minimum_balance(GreetingAccount::LEN),
+                space,
+                program_id,
+            );
+            msg!("Creating greeted account {}", account.key);
+            invoke(&create_ix, &[payer.clone(), account.clone(), system_program.clone()])?;
+        }
+
+        Self::process_greeting(program_id, &[account.clone(), payer.clone()], amount, &[])
+    }
+
+    fn credit_referrer<'a>(
+        program_id: &Pubkey,
+        referrer: &AccountInfo<'a>,
+        referrer_counter: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+    ) -> ProgramResult {
+        // Otherwise anyone could credit any referrer
+        if !referrer.is_signer {
+            msg!("Referrer {} must sign to be credited", referrer.key);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        let (expected_counter, bump) = referrer_counter_address(program_id, referrer.key);
+        if *referrer_counter.key != expected_counter {
+            msg!("Referrer counter is not the PDA of {}", referrer.key);
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        // The counter is created the first time the referrer brings someone in, at their expense
+        if *referrer_counter.owner == system_program::id() {
+            let create_ix = system_instruction::create_account(
+                referrer.key,
+                referrer_counter.key,
+                Rent::get()?.minimum_balance(GreetingAccount::LEN),
+                GreetingAccount::LEN as u64,
+                program_id,
+            );
+            msg!("Creating referrer counter {}", referrer_counter.key);
+            invoke_signed(
+                &create_ix,
+                &[referrer.clone(), referrer_counter.clone(), system_program.clone()],
+                &[&[REFERRER_SEED, referrer.key.as_ref(), &[bump]]],
+            )?;
+        }
+        if referrer_counter.owner != program_id {
+            msg!("Referrer counter does not have the correct program id");
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        let mut referrer_account = GreetingAccount::unpack_unchecked(&referrer_counter.data.borrow())?;
+        referrer_account.counter = referrer_account
+            .counter
+            .checked_add(1)
+            .ok_or(GreetingError::CounterOverflow)?;
+        GreetingAccount::pack(referrer_account, &mut referrer_counter.data.borrow_mut())?;
+
+        msg!("Referred by {}!", referrer.key);
+        Ok(())
+    }
+
+    pub fn process_distribute(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        per_account_amount: u64,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let source = next_account_info(accounts_iter)?;
+        let destinations = accounts_iter.as_slice();
+
+        if !source.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        if source.owner != program_id {
+            msg!("Source account does not have the correct program id");
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        let total = per_account_amount
+            .checked_mul(destinations.len() as u64)
+            .ok_or(GreetingError::CounterOverflow)?;
+
+        // Debit the whole amount first so a source also listed as a destination nets out correctly
+        let mut source_account = GreetingAccount::unpack_unchecked(&source.data.borrow())?;
+        source_account.free_counter = source_account
+            .free_counter
+            .checked_sub(total)
+            .ok_or(GreetingError::CounterUnderflow)?;
+        GreetingAccount::pack(source_account, &mut source.data.borrow_mut())?;
+
+        for destination in destinations {
+            if destination.owner != program_id {
+                msg!("Destination {} does not have the correct program id", destination.key);
+                return Err(ProgramError::IncorrectProgramId);
+            }
+            let mut destination_account = GreetingAccount::unpack_unchecked(&destination.data.borrow())?;
+            destination_account.free_counter = destination_account
+                .free_counter
+                .checked_add(per_account_amount)
+                .ok_or(GreetingError::CounterOverflow)?;
+            GreetingAccount::pack(destination_account, &mut destination.data.borrow_mut())?;
+        }
+
+        msg!("Distributed {} to {} account(s)", per_account_amount, destinations.len());
+        Ok(())
+    }
+
+    pub fn process_spend(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        amount: u64,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let account = next_account_info(accounts_iter)?;
+
+        if !account.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        if account.owner != program_id {
+            msg!("Greeted account does not have the correct program id");
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        // Only the credits are spent, the greeting counter is left alone
+        let mut greeting_account = GreetingAccount::unpack_unchecked(&account.data.borrow())?;
+        greeting_account.free_counter = greeting_account
+            .free_counter
+            .checked_sub(amount)
+            .ok_or(GreetingError::CounterUnderflow)?;
+        msg!("Spent {}, free counter: {}", amount, greeting_account.free_counter);
+        GreetingAccount::pack(greeting_account, &mut account.data.borrow_mut())?;
 
         Ok(())
     }
+
+    pub fn process_record_greeting(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let account = next_account_info(accounts_iter)?;
+        let stats_account = next_account_info(accounts_iter)?;
+
+        if account.owner != program_id {
+            msg!("Greeted account does not have the correct program id");
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        if stats_account.owner != program_id {
+            msg!("Stats account does not have the correct program id");
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        let mut greeting_account = GreetingAccount::unpack_unchecked(&account.data.borrow())?;
+        let now = Clock::get()?.unix_timestamp;
+        if greeting_account.cooldown_active(now) {
+            msg!("Greeted too recently, last greeting at {}", greeting_account.last_greeted_ts);
+            return Err(GreetingError::CooldownActive.into());
+        }
+        let first_greeting = greeting_account.counter == 0;
+        greeting_account.counter = greeting_account
+            .counter
+            .checked_add(1)
+            .ok_or(GreetingError::CounterOverflow)?;
+        greeting_account.record_streak(now)?;
+        GreetingAccount::pack(greeting_account, &mut account.data.borrow_mut())?;
+
+        let mut stats = GreetingStats::unpack_unchecked(&stats_account.data.borrow())?;
+        stats.total_greetings = stats
+            .total_greetings
+            .checked_add(1)
+            .ok_or(GreetingError::CounterOverflow)?;
+        if first_greeting {
+            stats.unique_greeter_count = stats
+                .unique_greeter_count
+                .checked_add(1)
+                .ok_or(GreetingError::CounterOverflow)?;
+        }
+        msg!("Total greetings: {}, unique greeters: {}", stats.total_greetings, stats.unique_greeter_count);
+        GreetingStats::pack(stats, &mut stats_account.data.borrow_mut())?;
+
+        Ok(())
+    }
+
+    pub fn process_version() -> ProgramResult {
+        set_return_data(&VERSION.to_le_bytes());
+        Ok(())
+    }
+
+    pub fn process_get_counter(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let account = next_account_info(accounts_iter)?;
+
+        if account.owner != program_id {
+            msg!("Greeted account does not have the correct program id");
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        // Read only, the account data is never written back
+        let greeting_account = GreetingAccount::unpack_unchecked(&account.data.borrow())?;
+        msg!("counter={}", greeting_account.counter);
+        msg!("free_counter={}", greeting_account.free_counter);
+
+        Ok(())
+    }
+}
+
+/// Address of the PDA counting the greetings brought in by `referrer`
+pub fn referrer_counter_address(program_id: &Pubkey, referrer: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[REFERRER_SEED, referrer.as_ref()], program_id)
+}
+
+/// Logs a machine-parseable greeting event for off-chain indexers.
+///
+/// The line is emitted in a stable format that will not change between releases:
+///
+/// `GREETING_EVENT account=<base58 pubkey> counter=<u32> free=<u64>`
+pub fn emit_greeting_event(account: &Pubkey, counter: u32, free_counter: u64) {
+    msg!("GREETING_EVENT account={} counter={} free={}", account, counter, free_counter);
 }
 
 
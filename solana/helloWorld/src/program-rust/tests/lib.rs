@@ -1,14 +1,66 @@
+mod program_test;
+
 use borsh::BorshDeserialize;
-use helloworld::{process_instruction, GreetingAccount};
+use helloworld::{
+    referrer_counter_address, GreetingAccount, GreetingError, GreetingInstruction, Processor,
+    GREETING_COOLDOWN, STREAK_WINDOW, VERSION,
+};
+use solana_program::{program_error::ProgramError, program_pack::Pack};
 use solana_program_test::*;
 use solana_sdk::{
     account::Account,
-    instruction::{AccountMeta, Instruction},
+    instruction::{AccountMeta, Instruction, InstructionError},
     pubkey::Pubkey,
-    signature::Signer,
-    transaction::Transaction,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    sysvar,
+    transaction::{Transaction, TransactionError},
 };
-use std::mem;
+use program_test::{
+    distribute_instruction, init_and_greet_instruction, init_greeting_data, init_greeting_instruction,
+    init_greeting_with_referrer_instruction, record_greeting_instruction, spend_instruction,
+    GreetingProgramTest,
+};
+use std::{convert::TryFrom, mem};
+
+// Greets an account holding `lamports`, optionally passing the rent sysvar
+async fn greet_with_lamports(lamports: u64, with_rent: bool) -> Result<(), TransactionError> {
+    let program_id = Pubkey::new_unique();
+    let greeted_pubkey = Pubkey::new_unique();
+
+    let mut program_test = ProgramTest::new(
+        "helloworld",
+        program_id,
+        processor!(Processor::process),
+    );
+    program_test.add_account(
+        greeted_pubkey,
+        Account {
+            lamports,
+            data: vec![0_u8; GreetingAccount::LEN],
+            owner: program_id,
+            ..Account::default()
+        },
+    );
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let mut accounts = vec![
+        AccountMeta::new(greeted_pubkey, false),
+        AccountMeta::new_readonly(payer.pubkey(), false),
+    ];
+    if with_rent {
+        accounts.push(AccountMeta::new_readonly(sysvar::rent::id(), false));
+    }
+    let mut transaction = Transaction::new_with_payer(
+        &[Instruction::new_with_bytes(program_id, &init_greeting_data(1), accounts)],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    banks_client
+        .process_transaction(transaction)
+        .await
+        .map_err(|e| e.unwrap())
+}
 
 #[tokio::test]
 async fn test_helloworld() {
@@ -18,7 +70,7 @@ async fn test_helloworld() {
     let mut program_test = ProgramTest::new(
         "helloworld", // Run the BPF version with `cargo test-bpf`
         program_id,
-        processor!(process_instruction), // Run the native version with `cargo test`
+        processor!(Processor::process), // Run the native version with `cargo test`
     );
     program_test.add_account(
         greeted_pubkey,
@@ -94,3 +146,602 @@ async fn test_helloworld() {
         2
     );
 }
+
+#[tokio::test]
+async fn test_emit_greeting_event() {
+    // msg! needs a running program once program-test has installed its syscall stubs,
+    // so the event is checked in the logs of a real greeting
+    let mut pt = GreetingProgramTest::start_new().await;
+    let greeted_pubkey = pt.create_greeting_account().await;
+    let greeter = pt.context.payer.pubkey();
+
+    pt.assert_log_contains(
+        &[init_greeting_instruction(&pt.program_id, &greeted_pubkey, &greeter, 1)],
+        &[],
+        &format!("Program log: GREETING_EVENT account={} counter=1 free=", greeted_pubkey),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_greeting_with_rent_exempt_account() {
+    let lamports = Rent::default().minimum_balance(GreetingAccount::LEN);
+    assert_eq!(greet_with_lamports(lamports, true).await, Ok(()));
+}
+
+#[tokio::test]
+async fn test_greeting_rejects_non_rent_exempt_account() {
+    assert_eq!(
+        greet_with_lamports(5, true).await,
+        Err(TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(GreetingError::NotRentExempt as u32)
+        ))
+    );
+}
+
+#[tokio::test]
+async fn test_greeting_without_rent_account_skips_check() {
+    assert_eq!(greet_with_lamports(5, false).await, Ok(()));
+}
+
+#[tokio::test]
+async fn test_get_counter_does_not_mutate() {
+    let program_id = Pubkey::new_unique();
+    let greeted_pubkey = Pubkey::new_unique();
+
+    let mut data = vec![0_u8; GreetingAccount::LEN];
+    GreetingAccount { counter: 3, free_counter: 42, ..GreetingAccount::default() }.pack_into_slice(&mut data);
+
+    let mut program_test = ProgramTest::new(
+        "helloworld",
+        program_id,
+        processor!(Processor::process),
+    );
+    program_test.add_account(
+        greeted_pubkey,
+        Account {
+            lamports: Rent::default().minimum_balance(GreetingAccount::LEN),
+            data: data.clone(),
+            owner: program_id,
+            ..Account::default()
+        },
+    );
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[Instruction::new_with_bytes(
+            program_id,
+            &[4],
+            vec![AccountMeta::new_readonly(greeted_pubkey, false)],
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    let greeted_account = banks_client
+        .get_account(greeted_pubkey)
+        .await
+        .expect("get_account")
+        .expect("greeted_account not found");
+    assert_eq!(greeted_account.data, data);
+}
+
+#[test]
+fn test_unpack_distinguishes_missing_amount() {
+    assert_eq!(
+        GreetingInstruction::unpack(&[]).err(),
+        Some(ProgramError::from(GreetingError::InvalidInstruction))
+    );
+    assert_eq!(
+        GreetingInstruction::unpack(&[0, 1, 2, 3]).err(),
+        Some(ProgramError::from(GreetingError::MissingAmount))
+    );
+}
+
+#[test]
+fn test_instruction_pack_round_trip() {
+    let instructions = [
+        GreetingInstruction::InitGreeting { amount: 42 },
+        GreetingInstruction::Version,
+        GreetingInstruction::Distribute { per_account_amount: 7 },
+        GreetingInstruction::RecordGreeting,
+        GreetingInstruction::GetCounter,
+        GreetingInstruction::Spend { amount: u64::MAX },
+        GreetingInstruction::InitAndGreet { amount: 3, space: GreetingAccount::LEN as u64 },
+    ];
+    for instruction in instructions {
+        assert_eq!(GreetingInstruction::unpack(&instruction.pack()).unwrap(), instruction);
+    }
+
+    // what the client and the encode_greeting example send
+    assert_eq!(GreetingInstruction::InitGreeting { amount: 42 }.pack(), init_greeting_data(42));
+}
+
+#[test]
+fn test_unpack_rejects_trailing_bytes() {
+    let mut data = init_greeting_data(42);
+    assert!(matches!(
+        GreetingInstruction::unpack(&data),
+        Ok(GreetingInstruction::InitGreeting { amount: 42 })
+    ));
+
+    data.push(0);
+    assert_eq!(
+        GreetingInstruction::unpack(&data).err(),
+        Some(ProgramError::from(GreetingError::InvalidInstruction))
+    );
+    for tag in [1, 3, 4] {
+        assert_eq!(
+            GreetingInstruction::unpack(&[tag, 0]).err(),
+            Some(ProgramError::from(GreetingError::InvalidInstruction))
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_version_returns_version_constant() {
+    let program_id = Pubkey::new_unique();
+    let program_test = ProgramTest::new(
+        "helloworld",
+        program_id,
+        processor!(Processor::process),
+    );
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[Instruction::new_with_bytes(program_id, &[1], vec![])],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    let simulation = banks_client.simulate_transaction(transaction).await.unwrap();
+
+    let return_data = simulation
+        .simulation_details
+        .expect("simulation details")
+        .return_data
+        .expect("return data");
+    assert_eq!(return_data.program_id, program_id);
+    // trailing zero bytes are trimmed off return data, so VERSION = 1 comes back as [1]
+    let mut version = [0u8; 4];
+    version[..return_data.data.len()].copy_from_slice(&return_data.data);
+    assert_eq!(u32::from_le_bytes(version), VERSION);
+}
+
+#[tokio::test]
+async fn test_greeting_program_test_harness() {
+    let mut pt = GreetingProgramTest::start_new().await;
+    let greeted_pubkey = pt.create_greeting_account().await;
+    let greeter = pt.context.payer.pubkey();
+
+    pt.process_tx_and_assert_ok(
+        &[init_greeting_instruction(&pt.program_id, &greeted_pubkey, &greeter, 10)],
+        &[],
+    )
+    .await;
+
+    let greeting_account = pt.get_greeting_account(greeted_pubkey).await;
+    assert_eq!(greeting_account.counter, 1);
+    assert_eq!(greeting_account.free_counter, 10);
+}
+
+#[tokio::test]
+async fn test_greeting_with_referrer() {
+    let mut pt = GreetingProgramTest::start_new().await;
+    let greeter = pt.context.payer.pubkey();
+    let referrer = pt.create_referrer().await;
+    let (referrer_counter, _bump) = referrer_counter_address(&pt.program_id, &referrer.pubkey());
+    assert!(pt.context.banks_client.get_account(referrer_counter).await.unwrap().is_none());
+
+    // the first referral creates the counter, the next one only credits it
+    for expected_count in 1..=2 {
+        let greeted_pubkey = pt.create_greeting_account().await;
+        pt.process_tx_and_assert_ok(
+            &[init_greeting_with_referrer_instruction(&pt.program_id, &greeted_pubkey, &greeter, &referrer.pubkey(), 10)],
+            &[&referrer],
+        )
+        .await;
+
+        assert_eq!(pt.get_greeting_account(greeted_pubkey).await.counter, 1);
+        assert_eq!(pt.get_greeting_account(referrer_counter).await.counter, expected_count);
+    }
+
+    let counter_account = pt.context.banks_client.get_account(referrer_counter).await.unwrap().unwrap();
+    assert_eq!(counter_account.owner, pt.program_id);
+    assert_eq!(counter_account.lamports, pt.rent.minimum_balance(GreetingAccount::LEN));
+}
+
+#[tokio::test]
+async fn test_greeting_without_referrer() {
+    let mut pt = GreetingProgramTest::start_new().await;
+    let greeted_pubkey = pt.create_greeting_account().await;
+    let greeter = pt.context.payer.pubkey();
+    let referrer = pt.create_referrer().await;
+    let (referrer_counter, _bump) = referrer_counter_address(&pt.program_id, &referrer.pubkey());
+
+    pt.process_tx_and_assert_ok(
+        &[init_greeting_instruction(&pt.program_id, &greeted_pubkey, &greeter, 10)],
+        &[],
+    )
+    .await;
+
+    assert_eq!(pt.get_greeting_account(greeted_pubkey).await.counter, 1);
+    assert!(pt.context.banks_client.get_account(referrer_counter).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_greeting_rejects_wrong_referrer_counter() {
+    let mut pt = GreetingProgramTest::start_new().await;
+    let greeted_pubkey = pt.create_greeting_account().await;
+    let greeter = pt.context.payer.pubkey();
+    let referrer = pt.create_referrer().await;
+
+    // the counter PDA of someone else is passed for the referrer
+    let mut greet_ix = init_greeting_with_referrer_instruction(&pt.program_id, &greeted_pubkey, &greeter, &referrer.pubkey(), 10);
+    greet_ix.accounts[3].pubkey = referrer_counter_address(&pt.program_id, &Pubkey::new_unique()).0;
+
+    pt.process_tx_and_assert_err(
+        &[greet_ix],
+        &[&referrer],
+        TransactionError::InstructionError(0, InstructionError::InvalidSeeds),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_greeting_rejects_unsigned_referrer() {
+    let mut pt = GreetingProgramTest::start_new().await;
+    let greeted_pubkey = pt.create_greeting_account().await;
+    let greeter = pt.context.payer.pubkey();
+    let referrer = Pubkey::new_unique();
+
+    // naming someone as the referrer without their signature would let anyone inflate their count
+    let mut greet_ix = init_greeting_with_referrer_instruction(&pt.program_id, &greeted_pubkey, &greeter, &referrer, 10);
+    greet_ix.accounts[2].is_signer = false;
+
+    pt.process_tx_and_assert_err(
+        &[greet_ix],
+        &[],
+        TransactionError::InstructionError(0, InstructionError::MissingRequiredSignature),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_distribute_conserves_free_counter() {
+    let mut pt = GreetingProgramTest::start_new().await;
+    let source = Keypair::new();
+    pt.write_greeting_account(&source.pubkey(), &GreetingAccount { counter: 1, free_counter: 100, ..GreetingAccount::default() });
+    let mut destinations = vec![];
+    for _ in 0..3 {
+        destinations.push(pt.create_greeting_account().await);
+    }
+
+    pt.process_tx_and_assert_ok(
+        &[distribute_instruction(&pt.program_id, &source.pubkey(), &destinations, 10)],
+        &[&source],
+    )
+    .await;
+
+    let source_account = pt.get_greeting_account(source.pubkey()).await;
+    assert_eq!(source_account.free_counter, 70);
+    let mut total = source_account.free_counter;
+    for destination in destinations {
+        let destination_account = pt.get_greeting_account(destination).await;
+        assert_eq!(destination_account.free_counter, 10);
+        assert_eq!(destination_account.counter, 0);
+        total += destination_account.free_counter;
+    }
+    assert_eq!(total, 100);
+}
+
+#[tokio::test]
+async fn test_distribute_rejects_more_than_source_holds() {
+    let mut pt = GreetingProgramTest::start_new().await;
+    let source = Keypair::new();
+    pt.write_greeting_account(&source.pubkey(), &GreetingAccount { counter: 1, free_counter: 20, ..GreetingAccount::default() });
+    let mut destinations = vec![];
+    for _ in 0..3 {
+        destinations.push(pt.create_greeting_account().await);
+    }
+
+    pt.process_tx_and_assert_err(
+        &[distribute_instruction(&pt.program_id, &source.pubkey(), &destinations, 10)],
+        &[&source],
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(GreetingError::CounterUnderflow as u32),
+        ),
+    )
+    .await;
+
+    assert_eq!(pt.get_greeting_account(source.pubkey()).await.free_counter, 20);
+}
+
+#[tokio::test]
+async fn test_referrer_log_only_fires_with_referrer() {
+    let mut pt = GreetingProgramTest::start_new().await;
+    let greeted_pubkey = pt.create_greeting_account().await;
+    let greeter = pt.context.payer.pubkey();
+    let referrer = pt.create_referrer().await;
+
+    pt.assert_log_not_contains(
+        &[init_greeting_instruction(&pt.program_id, &greeted_pubkey, &greeter, 1)],
+        &[],
+        "Referred by",
+    )
+    .await;
+
+    let last_greeted_ts = pt.get_greeting_account(greeted_pubkey).await.last_greeted_ts;
+    pt.set_unix_timestamp(last_greeted_ts + GREETING_COOLDOWN).await;
+    pt.assert_log_contains(
+        &[init_greeting_with_referrer_instruction(&pt.program_id, &greeted_pubkey, &greeter, &referrer.pubkey(), 2)],
+        &[&referrer],
+        &format!("Referred by {}", referrer.pubkey()),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_greetings_within_window_build_streak() {
+    let mut pt = GreetingProgramTest::start_new().await;
+    let greeted_pubkey = pt.create_greeting_account().await;
+    let greeter = pt.context.payer.pubkey();
+    let start = pt.get_clock().await.unix_timestamp;
+
+    for (i, amount) in (1..=3).enumerate() {
+        pt.set_unix_timestamp(start + i as i64 * STREAK_WINDOW).await;
+        pt.process_tx_and_assert_ok(
+            &[init_greeting_instruction(&pt.program_id, &greeted_pubkey, &greeter, amount)],
+            &[],
+        )
+        .await;
+    }
+
+    let greeting_account = pt.get_greeting_account(greeted_pubkey).await;
+    assert_eq!(greeting_account.current_streak, 3);
+    assert_eq!(greeting_account.best_streak, 3);
+    assert_eq!(greeting_account.last_greeted_ts, start + 2 * STREAK_WINDOW);
+}
+
+#[tokio::test]
+async fn test_gap_resets_streak_but_keeps_best() {
+    let mut pt = GreetingProgramTest::start_new().await;
+    let greeted_pubkey = pt.create_greeting_account().await;
+    let greeter = pt.context.payer.pubkey();
+    let start = pt.get_clock().await.unix_timestamp;
+
+    for (i, amount) in (1..=2).enumerate() {
+        pt.set_unix_timestamp(start + i as i64 * GREETING_COOLDOWN).await;
+        pt.process_tx_and_assert_ok(
+            &[init_greeting_instruction(&pt.program_id, &greeted_pubkey, &greeter, amount)],
+            &[],
+        )
+        .await;
+    }
+
+    pt.set_unix_timestamp(start + GREETING_COOLDOWN + STREAK_WINDOW + 1).await;
+    pt.process_tx_and_assert_ok(
+        &[init_greeting_instruction(&pt.program_id, &greeted_pubkey, &greeter, 3)],
+        &[],
+    )
+    .await;
+
+    let greeting_account = pt.get_greeting_account(greeted_pubkey).await;
+    assert_eq!(greeting_account.counter, 3);
+    assert_eq!(greeting_account.current_streak, 1);
+    assert_eq!(greeting_account.best_streak, 2);
+}
+
+#[tokio::test]
+async fn test_greeting_cooldown() {
+    let mut pt = GreetingProgramTest::start_new().await;
+    let greeted_pubkey = pt.create_greeting_account().await;
+    let greeter = pt.context.payer.pubkey();
+
+    pt.process_tx_and_assert_ok(
+        &[init_greeting_instruction(&pt.program_id, &greeted_pubkey, &greeter, 1)],
+        &[],
+    )
+    .await;
+    let start = pt.get_greeting_account(greeted_pubkey).await.last_greeted_ts;
+
+    // one second short of the cooldown
+    pt.set_unix_timestamp(start + GREETING_COOLDOWN - 1).await;
+    pt.process_tx_and_assert_err(
+        &[init_greeting_instruction(&pt.program_id, &greeted_pubkey, &greeter, 2)],
+        &[],
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(GreetingError::CooldownActive as u32),
+        ),
+    )
+    .await;
+    assert_eq!(pt.get_greeting_account(greeted_pubkey).await.counter, 1);
+
+    pt.set_unix_timestamp(start + GREETING_COOLDOWN).await;
+    pt.process_tx_and_assert_ok(
+        &[init_greeting_instruction(&pt.program_id, &greeted_pubkey, &greeter, 3)],
+        &[],
+    )
+    .await;
+
+    let greeting_account = pt.get_greeting_account(greeted_pubkey).await;
+    assert_eq!(greeting_account.counter, 2);
+    assert_eq!(greeting_account.last_greeted_ts, start + GREETING_COOLDOWN);
+}
+
+#[tokio::test]
+async fn test_init_and_greet_creates_account() {
+    let mut pt = GreetingProgramTest::start_new().await;
+    let payer = pt.context.payer.pubkey();
+    let greeted = Keypair::new();
+
+    pt.process_tx_and_assert_ok(
+        &[init_and_greet_instruction(&pt.program_id, &payer, &greeted.pubkey(), 10)],
+        &[&greeted],
+    )
+    .await;
+
+    let account = pt
+        .context
+        .banks_client
+        .get_account(greeted.pubkey())
+        .await
+        .unwrap()
+        .expect("greeted account not created");
+    assert_eq!(account.owner, pt.program_id);
+    assert_eq!(account.data.len(), GreetingAccount::LEN);
+    assert!(pt.rent.is_exempt(account.lamports, account.data.len()));
+
+    let greeting_account = pt.get_greeting_account(greeted.pubkey()).await;
+    assert_eq!(greeting_account.counter, 1);
+    assert_eq!(greeting_account.free_counter, 10);
+
+    // the account exists now, it is greeted again without being recreated
+    pt.set_unix_timestamp(greeting_account.last_greeted_ts + GREETING_COOLDOWN).await;
+    pt.process_tx_and_assert_ok(
+        &[init_and_greet_instruction(&pt.program_id, &payer, &greeted.pubkey(), 5)],
+        &[&greeted],
+    )
+    .await;
+
+    let greeting_account = pt.get_greeting_account(greeted.pubkey()).await;
+    assert_eq!(greeting_account.counter, 2);
+    assert_eq!(greeting_account.free_counter, 15);
+}
+
+#[tokio::test]
+async fn test_greeting_rejects_free_counter_overflow() {
+    let mut pt = GreetingProgramTest::start_new().await;
+    let greeted = Keypair::new();
+    pt.write_greeting_account(&greeted.pubkey(), &GreetingAccount { counter: 2, free_counter: u64::MAX - 5, ..GreetingAccount::default() });
+    let greeter = pt.context.payer.pubkey();
+
+    pt.process_tx_and_assert_err(
+        &[init_greeting_instruction(&pt.program_id, &greeted.pubkey(), &greeter, 6)],
+        &[],
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(GreetingError::CounterOverflow as u32),
+        ),
+    )
+    .await;
+
+    let greeting_account = pt.get_greeting_account(greeted.pubkey()).await;
+    assert_eq!(greeting_account.counter, 2);
+    assert_eq!(greeting_account.free_counter, u64::MAX - 5);
+}
+
+#[tokio::test]
+async fn test_spend_takes_credits() {
+    let mut pt = GreetingProgramTest::start_new().await;
+    let greeted = Keypair::new();
+    pt.write_greeting_account(&greeted.pubkey(), &GreetingAccount { counter: 2, free_counter: 50, ..GreetingAccount::default() });
+
+    pt.process_tx_and_assert_ok(&[spend_instruction(&pt.program_id, &greeted.pubkey(), 30)], &[&greeted])
+        .await;
+
+    let greeting_account = pt.get_greeting_account(greeted.pubkey()).await;
+    assert_eq!(greeting_account.free_counter, 20);
+    assert_eq!(greeting_account.counter, 2);
+}
+
+#[tokio::test]
+async fn test_spend_rejects_over_spend() {
+    let mut pt = GreetingProgramTest::start_new().await;
+    let greeted = Keypair::new();
+    pt.write_greeting_account(&greeted.pubkey(), &GreetingAccount { counter: 2, free_counter: 50, ..GreetingAccount::default() });
+
+    pt.process_tx_and_assert_err(
+        &[spend_instruction(&pt.program_id, &greeted.pubkey(), 51)],
+        &[&greeted],
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(GreetingError::CounterUnderflow as u32),
+        ),
+    )
+    .await;
+
+    assert_eq!(pt.get_greeting_account(greeted.pubkey()).await.free_counter, 50);
+}
+
+#[tokio::test]
+async fn test_record_greeting_counts_first_time_greeters() {
+    let mut pt = GreetingProgramTest::start_new().await;
+    let stats = pt.create_stats_account();
+    let first = pt.create_greeting_account().await;
+    let second = pt.create_greeting_account().await;
+
+    pt.process_tx_and_assert_ok(
+        &[
+            record_greeting_instruction(&pt.program_id, &first, &stats),
+            record_greeting_instruction(&pt.program_id, &second, &stats),
+        ],
+        &[],
+    )
+    .await;
+
+    let greeting_stats = pt.get_greeting_stats(stats).await;
+    assert_eq!(greeting_stats.total_greetings, 2);
+    assert_eq!(greeting_stats.unique_greeter_count, 2);
+    assert_eq!(pt.get_greeting_account(first).await.counter, 1);
+}
+
+#[tokio::test]
+async fn test_record_greeting_repeat_greeter_is_not_unique() {
+    let mut pt = GreetingProgramTest::start_new().await;
+    let stats = pt.create_stats_account();
+    let greeted = pt.create_greeting_account().await;
+    let record_ix = record_greeting_instruction(&pt.program_id, &greeted, &stats);
+
+    // recording the same account twice in a row runs into the cooldown
+    pt.process_tx_and_assert_err(
+        &[record_ix.clone(), record_ix.clone()],
+        &[],
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(GreetingError::CooldownActive as u32),
+        ),
+    )
+    .await;
+
+    pt.process_tx_and_assert_ok(std::slice::from_ref(&record_ix), &[]).await;
+    let start = pt.get_greeting_account(greeted).await.last_greeted_ts;
+    assert_ne!(start, 0);
+
+    // once it has passed the repeat greeting counts, this time with a non zero counter;
+    // the transaction is the same as the first one, so it needs a fresh blockhash
+    pt.set_unix_timestamp(start + GREETING_COOLDOWN).await;
+    pt.context.last_blockhash = pt
+        .context
+        .banks_client
+        .get_new_latest_blockhash(&pt.context.last_blockhash)
+        .await
+        .unwrap();
+    pt.process_tx_and_assert_ok(&[record_ix], &[]).await;
+
+    let greeting_stats = pt.get_greeting_stats(stats).await;
+    assert_eq!(greeting_stats.total_greetings, 2);
+    assert_eq!(greeting_stats.unique_greeter_count, 1);
+    let greeting_account = pt.get_greeting_account(greeted).await;
+    assert_eq!(greeting_account.counter, 2);
+    assert_eq!(greeting_account.last_greeted_ts, start + GREETING_COOLDOWN);
+}
+
+#[test]
+fn test_greeting_error_round_trips_through_code() {
+    let variants = [
+        GreetingError::InvalidInstruction,
+        GreetingError::NotRentExempt,
+        GreetingError::MissingAmount,
+        GreetingError::CounterOverflow,
+        GreetingError::CounterUnderflow,
+        GreetingError::CooldownActive,
+    ];
+    for variant in variants.iter() {
+        assert_eq!(GreetingError::try_from(*variant as u32).unwrap(), *variant);
+    }
+    assert_eq!(GreetingError::try_from(variants.len() as u32), Err(()));
+}
@@ -0,0 +1,318 @@
+use assert_matches::assert_matches;
+use helloworld::{
+    referrer_counter_address, GreetingAccount, GreetingInstruction, GreetingStats, Processor,
+};
+use solana_program::{clock::Clock, program_pack::Pack, rent::Rent};
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::{AccountSharedData, WritableAccount},
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction, system_program,
+    transaction::{Transaction, TransactionError},
+};
+
+pub struct GreetingProgramTest {
+    pub context: ProgramTestContext,
+    pub rent: Rent,
+    pub program_id: Pubkey,
+}
+
+impl GreetingProgramTest {
+    pub async fn start_new() -> Self {
+        let program_id = Pubkey::new_unique();
+        let pt = ProgramTest::new("helloworld", program_id, processor!(Processor::process));
+        let mut context = pt.start_with_context().await;
+        let rent = context.banks_client.get_rent().await.unwrap();
+
+        Self {
+            context,
+            rent,
+            program_id,
+        }
+    }
+
+    pub async fn process_tx_and_assert_ok(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) {
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+
+        let tx = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            self.context.last_blockhash,
+        );
+
+        assert_matches!(
+            self.context.banks_client.process_transaction(tx).await,
+            Ok(())
+        );
+    }
+
+    // processes the transaction, asserts it succeeded and returns the program logs it emitted
+    pub async fn process_tx_and_return_logs(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Vec<String> {
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+
+        let tx = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            self.context.last_blockhash,
+        );
+
+        // the banks client only hands out logs for simulations, so the transaction is simulated
+        // first and then sent for real
+        let simulation = self
+            .context
+            .banks_client
+            .simulate_transaction(tx.clone())
+            .await
+            .unwrap();
+        let logs = simulation.simulation_details.expect("simulation details").logs;
+        assert_matches!(simulation.result, Some(Ok(())), "{:?}", logs);
+        assert_matches!(self.context.banks_client.process_transaction(tx).await, Ok(()));
+        logs
+    }
+
+    pub async fn assert_log_contains(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+        needle: &str,
+    ) {
+        let logs = self.process_tx_and_return_logs(instructions, signers).await;
+        assert!(
+            logs.iter().any(|log| log.contains(needle)),
+            "no log contains {:?}: {:#?}",
+            needle,
+            logs
+        );
+    }
+
+    pub async fn assert_log_not_contains(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+        needle: &str,
+    ) {
+        let logs = self.process_tx_and_return_logs(instructions, signers).await;
+        assert!(
+            !logs.iter().any(|log| log.contains(needle)),
+            "a log contains {:?}: {:#?}",
+            needle,
+            logs
+        );
+    }
+
+    pub async fn process_tx_and_assert_err(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+        transaction_error: TransactionError,
+    ) {
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+
+        let tx = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            self.context.last_blockhash,
+        );
+
+        assert_eq!(
+            transaction_error,
+            self.context
+                .banks_client
+                .process_transaction(tx)
+                .await
+                .unwrap_err()
+                .unwrap(),
+        );
+    }
+
+    // a zeroed, rent exempt account owned by the program, ready to be greeted
+    pub async fn create_greeting_account(&mut self) -> Pubkey {
+        let greeted_keypair = Keypair::new();
+        let create_ix = system_instruction::create_account(
+            &self.context.payer.pubkey(),
+            &greeted_keypair.pubkey(),
+            self.rent.minimum_balance(GreetingAccount::LEN),
+            GreetingAccount::LEN as u64,
+            &self.program_id,
+        );
+        self.process_tx_and_assert_ok(&[create_ix], &[&greeted_keypair]).await;
+        greeted_keypair.pubkey()
+    }
+
+    // writes the greeting state directly, e.g. to start from a non zero free counter
+    pub fn write_greeting_account(&mut self, pubkey: &Pubkey, greeting_account: &GreetingAccount) {
+        let mut account = AccountSharedData::new(
+            self.rent.minimum_balance(GreetingAccount::LEN),
+            GreetingAccount::LEN,
+            &self.program_id,
+        );
+        greeting_account.pack_into_slice(account.data_as_mut_slice());
+        self.context.set_account(pubkey, &account);
+    }
+
+    // a funded referrer, the program creates its counter PDA on the first greeting it refers
+    pub async fn create_referrer(&mut self) -> Keypair {
+        let referrer = Keypair::new();
+        let fund_ix = system_instruction::transfer(
+            &self.context.payer.pubkey(),
+            &referrer.pubkey(),
+            self.rent.minimum_balance(GreetingAccount::LEN) * 10,
+        );
+        self.process_tx_and_assert_ok(&[fund_ix], &[]).await;
+        referrer
+    }
+
+    // a zeroed stats account owned by the program
+    pub fn create_stats_account(&mut self) -> Pubkey {
+        let stats = Pubkey::new_unique();
+        let account = AccountSharedData::new(
+            self.rent.minimum_balance(GreetingStats::LEN),
+            GreetingStats::LEN,
+            &self.program_id,
+        );
+        self.context.set_account(&stats, &account);
+        stats
+    }
+
+    pub async fn get_greeting_stats(&mut self, pubkey: Pubkey) -> GreetingStats {
+        let account = self
+            .context
+            .banks_client
+            .get_account(pubkey)
+            .await
+            .unwrap()
+            .expect("stats account not found");
+        GreetingStats::unpack_unchecked(&account.data).unwrap()
+    }
+
+    pub async fn get_clock(&mut self) -> Clock {
+        self.context.banks_client.get_sysvar::<Clock>().await.unwrap()
+    }
+
+    pub async fn set_unix_timestamp(&mut self, unix_timestamp: i64) {
+        let mut clock = self.get_clock().await;
+        clock.unix_timestamp = unix_timestamp;
+        self.context.set_sysvar(&clock);
+    }
+
+    pub async fn get_greeting_account(&mut self, pubkey: Pubkey) -> GreetingAccount {
+        let account = self
+            .context
+            .banks_client
+            .get_account(pubkey)
+            .await
+            .unwrap()
+            .expect("greeted_account not found");
+        GreetingAccount::unpack_unchecked(&account.data).unwrap()
+    }
+}
+
+pub fn init_greeting_data(amount: u64) -> Vec<u8> {
+    let mut data = vec![0];
+    data.extend_from_slice(&amount.to_le_bytes());
+    data
+}
+
+pub fn init_greeting_instruction(
+    program_id: &Pubkey,
+    greeted: &Pubkey,
+    greeter: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &init_greeting_data(amount),
+        vec![
+            AccountMeta::new(*greeted, false),
+            AccountMeta::new_readonly(*greeter, false),
+        ],
+    )
+}
+
+pub fn init_greeting_with_referrer_instruction(
+    program_id: &Pubkey,
+    greeted: &Pubkey,
+    greeter: &Pubkey,
+    referrer: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let (referrer_counter, _bump) = referrer_counter_address(program_id, referrer);
+    Instruction::new_with_bytes(
+        *program_id,
+        &init_greeting_data(amount),
+        vec![
+            AccountMeta::new(*greeted, false),
+            AccountMeta::new_readonly(*greeter, false),
+            AccountMeta::new(*referrer, true),
+            AccountMeta::new(referrer_counter, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn distribute_instruction(
+    program_id: &Pubkey,
+    source: &Pubkey,
+    destinations: &[Pubkey],
+    per_account_amount: u64,
+) -> Instruction {
+    let mut data = vec![2];
+    data.extend_from_slice(&per_account_amount.to_le_bytes());
+    let mut accounts = vec![AccountMeta::new(*source, true)];
+    accounts.extend(destinations.iter().map(|destination| AccountMeta::new(*destination, false)));
+    Instruction::new_with_bytes(*program_id, &data, accounts)
+}
+
+pub fn spend_instruction(program_id: &Pubkey, greeted: &Pubkey, amount: u64) -> Instruction {
+    let mut data = vec![5];
+    data.extend_from_slice(&amount.to_le_bytes());
+    Instruction::new_with_bytes(*program_id, &data, vec![AccountMeta::new(*greeted, true)])
+}
+
+pub fn record_greeting_instruction(program_id: &Pubkey, greeted: &Pubkey, stats: &Pubkey) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &[3],
+        vec![
+            AccountMeta::new(*greeted, false),
+            AccountMeta::new(*stats, false),
+        ],
+    )
+}
+
+pub fn init_and_greet_instruction(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    greeted: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &GreetingInstruction::InitAndGreet {
+            amount,
+            space: GreetingAccount::LEN as u64,
+        }
+        .pack(),
+        vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(*greeted, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}